@@ -1,15 +1,33 @@
-use core::{mem, ptr};
+use core::{cmp, mem, ptr, slice};
+use core::ops::Try;
+#[cfg(not(feature = "minimal"))]
 use orbclient::{Color, Renderer};
-use std::fs::find;
 use std::proto::Protocol;
+use std::vec::Vec;
 use uefi::guid::Guid;
+use uefi::memory::MemoryType;
 use uefi::status::{Error, Result};
 
-use crate::display::{Display, ScaledDisplay, Output};
+use crate::config::{config, Console};
+use crate::device_path;
+#[cfg(not(feature = "minimal"))]
+use crate::display::{Backend, Display, ScaledDisplay};
+use crate::display::Output;
+use crate::error::BootResult;
+#[cfg(not(feature = "minimal"))]
+use crate::font;
+use crate::fs::find_file;
+#[cfg(not(feature = "minimal"))]
 use crate::image::{self, Image};
 use crate::key::{key, Key};
+use crate::proto::locate_handles;
+use crate::rng;
 use crate::redoxfs;
-use crate::text::TextDisplay;
+use crate::secret;
+#[cfg(not(feature = "minimal"))]
+use crate::text::{text_area, TextDisplay};
+use crate::time;
+use crate::watchdog;
 
 use self::memory_map::memory_map;
 use self::paging::paging;
@@ -18,7 +36,7 @@ mod memory_map;
 mod paging;
 mod partitions;
 
-static KERNEL: &'static str = concat!("\\", env!("BASEDIR"), "\\kernel");
+#[cfg(not(feature = "minimal"))]
 static SPLASHBMP: &'static [u8] = include_bytes!("../../../res/splash.bmp");
 
 static KERNEL_OFFSET: u64 = 0xFFFF_FF00_0000_0000;
@@ -29,37 +47,99 @@ static mut KERNEL_ENTRY: u64 = 0;
 
 static mut DTB_PHYSICAL: u64 = 0;
 
+static mut RNG_SEED_PHYSICAL: u64 = 0;
+static mut RNG_SEED_QUALITY: u8 = 0;
+
 #[no_mangle]
 pub extern "C" fn __chkstk() {
     //TODO
 }
 
-unsafe fn exit_boot_services(key: usize) {
+/// Times to fetch the memory map and call ExitBootServices before giving up
+const EXIT_BOOT_SERVICES_ATTEMPTS: usize = 4;
+
+/// Exit boot services, fetching the memory map again when the firmware rejects a stale key
+unsafe fn exit_boot_services() {
     let handle = std::handle();
     let uefi = std::system_table();
 
-    let _ = (uefi.BootServices.ExitBootServices)(handle, key);
+    for _ in 0..EXIT_BOOT_SERVICES_ATTEMPTS {
+        let key = memory_map();
+        if (uefi.BootServices.ExitBootServices)(handle, key).branch().is_continue() {
+            return;
+        }
+    }
+
+    // Nothing but GetMemoryMap may be called after a failed ExitBootServices
+    loop {
+        asm!("wfi");
+    }
 }
 
-unsafe fn enter() -> ! {
-    let entry_fn: extern "C" fn(dtb: u64) -> ! = mem::transmute((
-        KERNEL_PHYSICAL + KERNEL_ENTRY - KERNEL_OFFSET
-    ));
-    entry_fn(DTB_PHYSICAL);
+/// Execution state of EL1 is AArch64
+const HCR_EL2_RW: u64 = 1 << 31;
+/// Let EL1 read the physical counter and use the physical timer
+const CNTHCTL_EL2_EL1PCTEN_EL1PCEN: u64 = 0b11;
+/// SCTLR_EL1 with only its RES1 bits set, so EL1 starts with the MMU and caches off and
+/// little endian, whatever the firmware left there
+const SCTLR_EL1_RES1: u64 = 0x30D0_0800;
+/// Return to EL1 using SP_EL1, with debug, SError, IRQ and FIQ masked
+const SPSR_EL1H_DAIF: u64 = 0b1111 << 6 | 0b0101;
+
+/// Exception level the bootloader is running at
+fn current_el() -> u64 {
+    let el: u64;
+    unsafe {
+        asm!("mrs {}, CurrentEL", out(reg) el);
+    }
+    (el >> 2) & 0b11
 }
 
-fn get_correct_block_io() -> Result<redoxfs::Disk> {
-    // Get all BlockIo handles.
-    let mut handles = vec! [uefi::Handle(0); 128];
-    let mut size = handles.len() * mem::size_of::<uefi::Handle>();
+/// Jump to the kernel with the DTB in x0, and the `rng::SEED_SIZE` byte RNG seed and its
+/// quality, one of the `rng::QUALITY_*` values, in x1 and x2
+unsafe fn enter() -> ! {
+    let entry = KERNEL_PHYSICAL + KERNEL_ENTRY - KERNEL_OFFSET;
+
+    // Many firmwares start us at EL2, but the kernel expects to run at EL1
+    if current_el() == 2 {
+        // The kernel keeps using this stack, and noreturn asm can not have outputs
+        let sp: u64;
+        asm!("mov {}, sp", out(reg) sp);
+
+        asm!(
+            "msr cnthctl_el2, {cnthctl}",
+            "msr cntvoff_el2, xzr",
+            "msr hcr_el2, {hcr}",
+            "msr sctlr_el1, {sctlr}",
+            "msr sp_el1, {sp}",
+            "msr spsr_el2, {spsr}",
+            "msr elr_el2, {entry}",
+            "isb",
+            "eret",
+            cnthctl = in(reg) CNTHCTL_EL2_EL1PCTEN_EL1PCEN,
+            hcr = in(reg) HCR_EL2_RW,
+            sctlr = in(reg) SCTLR_EL1_RES1,
+            spsr = in(reg) SPSR_EL1H_DAIF,
+            entry = in(reg) entry,
+            sp = in(reg) sp,
+            in("x0") DTB_PHYSICAL,
+            in("x1") RNG_SEED_PHYSICAL,
+            in("x2") RNG_SEED_QUALITY as u64,
+            options(noreturn)
+        );
+    }
 
-    (std::system_table().BootServices.LocateHandle)(uefi::boot::LocateSearchType::ByProtocol, &uefi::guid::BLOCK_IO_GUID, 0, &mut size, handles.as_mut_ptr())?;
+    let entry_fn: extern "C" fn(dtb: u64, rng_seed: u64, rng_seed_quality: u64) -> ! = mem::transmute(entry);
+    entry_fn(DTB_PHYSICAL, RNG_SEED_PHYSICAL, RNG_SEED_QUALITY as u64);
+}
 
-    let max_size = size / mem::size_of::<uefi::Handle>();
-    let actual_size = std::cmp::min(handles.len(), max_size);
+fn get_correct_block_io() -> Result<redoxfs::Disk> {
+    // Get all BlockIo handles, in a stable order so the same partition is picked every boot
+    let mut handles = locate_handles(&uefi::guid::BLOCK_IO_GUID)?;
+    device_path::sort_handles(&mut handles);
 
     // Return the handle that seems bootable.
-    for handle in handles.into_iter().take(actual_size) {
+    for handle in handles {
         let block_io = redoxfs::Disk::handle_protocol(handle)?;
         if !block_io.0.Media.LogicalPartition {
             continue;
@@ -69,10 +149,17 @@ fn get_correct_block_io() -> Result<redoxfs::Disk> {
         if part.sys == 1 {
             continue;
         }
-        assert_eq!({part.rev}, partitions::PARTITION_INFO_PROTOCOL_REVISION);
+        if { part.rev } != partitions::PARTITION_INFO_PROTOCOL_REVISION {
+            warn!("Skipping handle {:X} with partition info revision {:X}", handle.0, { part.rev });
+            continue;
+        }
         if part.ty == partitions::PartitionProtoDataTy::Gpt as u32 {
             let gpt = unsafe { part.info.gpt };
-            assert_ne!(gpt.part_ty_guid, partitions::ESP_GUID, "detected esp partition again");
+            if gpt.part_ty_guid == partitions::ESP_GUID {
+                // Dual boot systems may have more than one ESP
+                info!("Skipping ESP at handle {:X}", handle.0);
+                continue;
+            }
             if gpt.part_ty_guid == partitions::REDOX_FS_GUID || gpt.part_ty_guid == partitions::LINUX_FS_GUID {
                 return Ok(block_io);
             }
@@ -85,7 +172,8 @@ fn get_correct_block_io() -> Result<redoxfs::Disk> {
             continue;
         }
     }
-    panic!("Couldn't find handle for partition");
+    error!("Couldn't find handle for partition");
+    Err(Error::NotFound)
 }
 
 static DTB_GUID: Guid = Guid(0xb1b621d5, 0xf19c, 0x41a5, [0x83, 0x0b, 0xd9, 0x15, 0x2c, 0x69, 0xaa, 0xe0]);
@@ -112,31 +200,103 @@ fn redoxfs() -> Result<redoxfs::FileSystem> {
 
 const MB: usize = 1024 * 1024;
 
+/// Kernel read buffer sizes, tried in order since fragmented firmware heaps may not have
+/// room for the larger ones
+const READ_BUFFER_SIZES: [usize; 3] = [4 * MB, MB, 256 * 1024];
+
+/// Allocate the largest read buffer the heap has room for
+fn read_buffer() -> Result<Vec<u8>> {
+    for &size in READ_BUFFER_SIZES.iter() {
+        let mut buf = Vec::new();
+        if buf.try_reserve_exact(size).is_ok() {
+            buf.resize(size, 0);
+            return Ok(buf);
+        }
+        println!("Failed to allocate a {} KB read buffer", size / 1024);
+    }
+    Err(Error::OutOfResources)
+}
+
+/// Put the RNG seed in its own page, which the kernel finds through `enter`
+fn store_rng_seed() -> Result<()> {
+    let uefi = std::system_table();
+
+    let mut ptr = 0;
+    (uefi.BootServices.AllocatePages)(
+        0, // AllocateAnyPages
+        MemoryType::EfiRuntimeServicesData, // Keeps this memory out of free space list
+        1,
+        &mut ptr
+    )?;
+
+    let (seed, quality) = rng::seed();
+    unsafe {
+        ptr::write_bytes(ptr as *mut u8, 0, 4096);
+        ptr::copy(seed.as_ptr(), ptr as *mut u8, seed.len());
+        RNG_SEED_PHYSICAL = ptr as u64;
+        RNG_SEED_QUALITY = quality;
+        println!("RNG seed: {:X} quality {}", RNG_SEED_PHYSICAL, RNG_SEED_QUALITY);
+    }
+    Ok(())
+}
+
+/// Allocate pages for the kernel, for when the heap has no room for it
+fn allocate_kernel_pages(len: usize) -> Result<&'static mut [u8]> {
+    let uefi = std::system_table();
+
+    let mut ptr = 0;
+    (uefi.BootServices.AllocatePages)(
+        0, // AllocateAnyPages
+        MemoryType::EfiLoaderData,
+        (len + 4095) / 4096,
+        &mut ptr
+    )?;
+
+    Ok(unsafe { slice::from_raw_parts_mut(ptr as *mut u8, len) })
+}
+
 fn inner() -> Result<()> {
     find_dtb()?;
 
     {
         println!("Loading Kernel...");
-        let (kernel, mut env): (Vec<u8>, String) = {
-            let (_i, mut kernel_file) = find(KERNEL)?;
+        // Outlives the block, since the kernel may be read into it
+        let mut kernel_vec = Vec::new();
+        let (kernel, mut env): (&[u8], String) = {
+            let mut kernel_file = find_file(&config().kernel_esp_path())?;
             let info = kernel_file.info()?;
-            let len = info.FileSize;
-            let mut kernel = Vec::with_capacity(len as usize);
-            let mut buf = vec![0; 4 * MB];
-            loop {
-                let percent = kernel.len() as u64 * 100 / len;
-                print!("\r{}% - {} MB", percent, kernel.len() / MB);
-
-                let count = kernel_file.read(&mut buf)?;
+            // A corrupt filesystem may report an absurd length, refuse it before allocating
+            if info.FileSize > config().max_kernel_size {
+                println!("Kernel size {} MB is over the maximum of {} MB", info.FileSize / MB as u64, config().max_kernel_size / MB as u64);
+                return Err(Error::BadBufferSize);
+            }
+            let len = info.FileSize as usize;
+
+            let (kernel, strategy): (&mut [u8], &str) = if kernel_vec.try_reserve_exact(len).is_ok() {
+                kernel_vec.resize(len, 0);
+                (&mut kernel_vec[..], "heap")
+            } else {
+                println!("Failed to allocate {} MB for the kernel on the heap", len / MB);
+                (allocate_kernel_pages(len)?, "pages")
+            };
+            let mut buf = read_buffer()?;
+
+            let mut i = 0;
+            while i < len {
+                print!("\r{}% - {} MB", i * 100 / len, i / MB);
+
+                let count = kernel_file.read(&mut buf[..cmp::min(buf.len(), len - i)])?;
                 if count == 0 {
                     break;
                 }
 
-                kernel.extend(&buf[.. count]);
+                kernel[i..i + count].copy_from_slice(&buf[..count]);
+                i += count;
             }
             println!("");
+            println!("Read kernel into {} with a {} KB buffer", strategy, buf.len() / 1024);
 
-            (kernel, String::new())
+            (&kernel[..i], String::new())
         };
 
         println!("Copying Kernel...");
@@ -151,9 +311,17 @@ fn inner() -> Result<()> {
         println!("Done!");
     }
 
+    store_rng_seed()?;
+
+    println!("Running at EL{}", current_el());
+    println!("Bootloader took {} ms", time::elapsed_ms());
+
+    // The kernel takes over from here, so the firmware must not reset it
+    watchdog::disarm();
+
     unsafe {
-        let key = memory_map();
-        exit_boot_services(key);
+        secret::scrub_all();
+        exit_boot_services();
     }
 
     unsafe {
@@ -171,7 +339,10 @@ fn select_mode(output: &mut Output) -> Result<u32> {
         for i in 0..output.0.Mode.MaxMode {
             let mut mode_ptr = ::core::ptr::null_mut();
             let mut mode_size = 0;
-            (output.0.QueryMode)(output.0, i, &mut mode_size, &mut mode_ptr)?;
+            // Some firmware leaves holes in the mode list, skip them
+            if (output.0.QueryMode)(output.0, i, &mut mode_size, &mut mode_ptr).branch().is_break() {
+                continue;
+            }
 
             let mode = unsafe { &mut *mode_ptr };
             let w = mode.HorizontalResolution;
@@ -188,19 +359,21 @@ fn select_mode(output: &mut Output) -> Result<u32> {
     }
 }
 
+#[cfg(not(feature = "minimal"))]
 fn pretty_pipe<T, F: FnMut() -> Result<T>>(splash: &Image, f: F) -> Result<T> {
-    let mut display = Display::new(Output::one()?);
+    let mut backend = Backend::one();
+    let mut display = Display::new(&mut backend);
 
     let mut display = ScaledDisplay::new(&mut display);
 
     {
-        let bg = Color::rgb(0x4a, 0xa3, 0xfd);
+        let config = config();
 
-        display.set(bg);
+        display.set(config.bg_color);
 
         {
-            let x = (display.width() as i32 - splash.width() as i32)/2;
-            let y = 16;
+            let x = config.splash_x.resolve(display.width() as i32, splash.width() as i32);
+            let y = config.splash_y.resolve(display.height() as i32, splash.height() as i32);
             splash.draw(&mut display, x, y);
         }
 
@@ -213,7 +386,7 @@ fn pretty_pipe<T, F: FnMut() -> Result<T>>(splash: &Image, f: F) -> Result<T> {
             let mut x = (display.width() as i32 - prompt.len() as i32 * 8)/2;
             let y = display.height() as i32 - 32;
             for c in prompt.chars() {
-                display.char(x, y, c, Color::rgb(0xff, 0xff, 0xff));
+                font::draw(&mut display, x, y, c, Color::rgb(0xff, 0xff, 0xff));
                 x += 8;
             }
         }
@@ -222,10 +395,9 @@ fn pretty_pipe<T, F: FnMut() -> Result<T>>(splash: &Image, f: F) -> Result<T> {
     }
 
     {
-        let cols = 80;
-        let off_x = (display.width() as i32 - cols as i32 * 8)/2;
-        let off_y = 16 + splash.height() as i32 + 16;
-        let rows = (display.height() as i32 - 64 - off_y - 1) as usize/16;
+        let splash_y = config().splash_y.resolve(display.height() as i32, splash.height() as i32);
+        let off_y = splash_y + splash.height() as i32 + 16;
+        let (off_x, cols, rows) = text_area(display.width(), display.height(), off_y);
         display.rect(off_x, off_y, cols as u32 * 8, rows as u32 * 16, Color::rgb(0, 0, 0));
         display.sync();
 
@@ -238,7 +410,11 @@ fn pretty_pipe<T, F: FnMut() -> Result<T>>(splash: &Image, f: F) -> Result<T> {
     }
 }
 
-pub fn main() -> Result<()> {
+pub fn main() -> BootResult<()> {
+    if config().console == Console::Serial {
+        warn!("console = serial is not supported on aarch64, messages stay on the firmware console");
+    }
+
     inner()?;
 
     /* TODO
@@ -0,0 +1,233 @@
+//! Read the ACPI tables, and copy them to memory the kernel will not reclaim
+
+use core::{mem, ptr, slice};
+use std::string::String;
+use std::vec::Vec;
+use uefi::status::Result;
+
+use crate::fs::{list_dir, read_file};
+use crate::loaded_image::resolve;
+
+use super::allocate_zero_pages;
+
+/// Size of the header shared by all system description tables
+const SDT_HEADER_SIZE: usize = 36;
+/// Most tables that are copied
+const MAX_TABLES: usize = 256;
+/// Most bytes of tables that are copied
+const MAX_TABLES_SIZE: usize = 4 * 1024 * 1024;
+/// FADT flag for platforms without the fixed ACPI hardware, such as most ARM systems
+const FADT_HW_REDUCED_ACPI: u32 = 1 << 20;
+
+/// Describes one table in the blob at `KernelArgs.acpi_tables_base`
+///
+/// The blob starts with a `u32` count and four bytes of padding, then one entry per
+/// table, then the tables themselves, each aligned to 8 bytes.
+#[derive(Clone, Copy, Debug)]
+#[repr(packed)]
+pub struct TableEntry {
+    pub signature: [u8; 4],
+    pub length: u32,
+    /// Offset of the table from the start of the blob
+    pub offset: u64,
+}
+
+/// Read the length of the table at `address`, if it is sane
+unsafe fn table_length(address: u64) -> Option<usize> {
+    if address == 0 {
+        return None;
+    }
+    let length = ptr::read_unaligned((address + 4) as *const u32) as usize;
+    if length < SDT_HEADER_SIZE || length > MAX_TABLES_SIZE {
+        None
+    } else {
+        Some(length)
+    }
+}
+
+/// Get the table at `address` if its checksum is valid
+unsafe fn read_table(address: u64) -> Option<&'static [u8]> {
+    let length = table_length(address)?;
+    let table = slice::from_raw_parts(address as *const u8, length);
+    let sum = table.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    if sum != 0 {
+        warn!("ACPI table {} at {:X} has an invalid checksum", String::from_utf8_lossy(&table[..4]), address);
+        return None;
+    }
+    Some(table)
+}
+
+/// Get the addresses of the tables in the XSDT, or in the RSDT if there is no XSDT
+unsafe fn root_entries(rsdt_addr: u32, xsdt_addr: u64) -> Vec<u64> {
+    let (root, entry_size) = if xsdt_addr != 0 {
+        (read_table(xsdt_addr), 8)
+    } else {
+        (read_table(rsdt_addr as u64), 4)
+    };
+    let root = match root {
+        Some(root) => root,
+        None => return Vec::new(),
+    };
+
+    root[SDT_HEADER_SIZE..].chunks_exact(entry_size).map(|entry| if entry_size == 8 {
+        ptr::read_unaligned(entry.as_ptr() as *const u64)
+    } else {
+        ptr::read_unaligned(entry.as_ptr() as *const u32) as u64
+    }).collect()
+}
+
+/// The DSDT is referenced by the FADT instead of the root table
+unsafe fn dsdt_address(fadt: &[u8]) -> u64 {
+    // X_DSDT is only present in ACPI 2.0 and later FADTs
+    if fadt.len() >= 148 {
+        let x_dsdt = ptr::read_unaligned(fadt.as_ptr().add(140) as *const u64);
+        if x_dsdt != 0 {
+            return x_dsdt;
+        }
+    }
+    if fadt.len() >= 44 {
+        ptr::read_unaligned(fadt.as_ptr().add(40) as *const u32) as u64
+    } else {
+        0
+    }
+}
+
+/// Check the FADT flags for a hardware-reduced platform, `None` if there is no valid FADT
+pub fn hardware_reduced(rsdt_addr: u32, xsdt_addr: u64) -> Option<bool> {
+    unsafe {
+        let fadt = root_entries(rsdt_addr, xsdt_addr).into_iter()
+            .filter_map(|address| read_table(address))
+            .find(|table| &table[..4] == b"FACP")?;
+        // ACPI 1.0 FADTs are too short to have the flags
+        if fadt.len() < 116 {
+            return Some(false);
+        }
+        Some(ptr::read_unaligned(fadt.as_ptr().add(112) as *const u32) & FADT_HW_REDUCED_ACPI != 0)
+    }
+}
+
+/// Check that an AML file holds exactly one table with a valid header and checksum
+fn valid_override(data: &[u8]) -> bool {
+    if data.len() < SDT_HEADER_SIZE || data.len() > MAX_TABLES_SIZE {
+        return false;
+    }
+    let length = unsafe { ptr::read_unaligned(data.as_ptr().add(4) as *const u32) } as usize;
+    let sum = data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    let signature = data[..4].iter().all(|byte| byte.is_ascii_uppercase() || byte.is_ascii_digit());
+    length == data.len() && sum == 0 && signature
+}
+
+/// Load replacement tables from the `acpi` directory on the ESP, skipping invalid ones
+pub fn load_overrides() -> Vec<Vec<u8>> {
+    let dir = resolve("acpi");
+    let names = match list_dir(&dir) {
+        Ok(names) => names,
+        Err(_) => {
+            warn!("No ACPI overrides found in {}", dir);
+            return Vec::new();
+        }
+    };
+
+    let mut overrides = Vec::new();
+    for name in names.iter().filter(|name| name.to_ascii_lowercase().ends_with(".aml")) {
+        let path = format!("{}\\{}", dir, name);
+        match read_file(&path) {
+            Ok(data) if valid_override(&data) => {
+                info!("ACPI override {} from {}", String::from_utf8_lossy(&data[..4]), path);
+                overrides.push(data);
+            },
+            Ok(_) => warn!("ACPI override {} has an invalid table header, skipping it", path),
+            Err(err) => warn!("Failed to read ACPI override {}: {:?}", path, err),
+        }
+    }
+    overrides
+}
+
+/// Check if `table` takes the place of `original`, by signature and OEM table ID
+fn replaces(table: &[u8], original: &[u8]) -> bool {
+    table[..4] == original[..4] && table[16..24] == original[16..24]
+}
+
+/// Copy every valid table referenced by the RSDP, returning the base and size of the blob.
+/// Each of `overrides` replaces the table with the same signature and OEM table ID, or
+/// is added if there is none.
+pub fn copy_tables(rsdt_addr: u32, xsdt_addr: u64, overrides: &[Vec<u8>], page_size: usize) -> Result<(u64, u64)> {
+    let mut tables: Vec<&[u8]> = Vec::new();
+    let mut total = 0;
+    let mut addresses = unsafe { root_entries(rsdt_addr, xsdt_addr) };
+    let mut i = 0;
+    while i < addresses.len() {
+        let address = addresses[i];
+        i += 1;
+
+        let original = match unsafe { read_table(address) } {
+            Some(table) => table,
+            None => continue,
+        };
+
+        let table = match overrides.iter().find(|data| replaces(data, original)) {
+            Some(data) => {
+                info!("ACPI table {} replaced by an override", String::from_utf8_lossy(&original[..4]));
+                &data[..]
+            },
+            None => original,
+        };
+
+        if tables.len() >= MAX_TABLES || total + table.len() > MAX_TABLES_SIZE {
+            warn!("ACPI tables are over the maximum, skipping the rest");
+            break;
+        }
+
+        // The DSDT is still found through the firmware FADT, so it can be overridden too
+        if &original[..4] == b"FACP" {
+            let dsdt = unsafe { dsdt_address(original) };
+            if !addresses.contains(&dsdt) {
+                addresses.push(dsdt);
+            }
+        }
+
+        total += (table.len() + 7) / 8 * 8;
+        tables.push(table);
+    }
+
+    // Overrides that did not replace anything are new tables
+    for data in overrides.iter() {
+        if tables.iter().any(|table| table.as_ptr() == data.as_ptr()) {
+            continue;
+        }
+        if tables.len() >= MAX_TABLES || total + data.len() > MAX_TABLES_SIZE {
+            warn!("ACPI tables are over the maximum, skipping the rest");
+            break;
+        }
+        total += (data.len() + 7) / 8 * 8;
+        tables.push(data);
+    }
+
+    if tables.is_empty() {
+        return Ok((0, 0));
+    }
+
+    let header_size = 8 + tables.len() * mem::size_of::<TableEntry>();
+    let size = header_size + total;
+    let base = unsafe { allocate_zero_pages((size + page_size - 1) / page_size)? };
+
+    let mut offset = header_size;
+    unsafe {
+        ptr::write(base as *mut u32, tables.len() as u32);
+        let entries = (base + 8) as *mut TableEntry;
+        for (i, table) in tables.iter().enumerate() {
+            let mut signature = [0; 4];
+            signature.copy_from_slice(&table[..4]);
+            ptr::write_unaligned(entries.add(i), TableEntry {
+                signature,
+                length: table.len() as u32,
+                offset: offset as u64,
+            });
+            ptr::copy(table.as_ptr(), (base + offset) as *mut u8, table.len());
+            debug!("ACPI table {} {:X}", String::from_utf8_lossy(&signature), table.len());
+            offset += (table.len() + 7) / 8 * 8;
+        }
+    }
+
+    Ok((base as u64, size as u64))
+}
@@ -0,0 +1,63 @@
+//! Check for the CPU features that `paging_enter` enables and the kernel relies on, so an
+//! unsupported CPU gets a message instead of a fault, and for optional ones
+
+use core::arch::x86_64::__cpuid;
+
+/// CPUID register holding a feature bit
+#[derive(Clone, Copy)]
+enum Register {
+    Ecx,
+    Edx,
+}
+
+/// A feature by name, CPUID leaf, register, and bit
+type Feature = (&'static str, u32, Register, u32);
+
+/// Features that are required.
+///
+/// Long mode, NX, PAE, PSE, and PGE are needed for the page tables and `EFER.NXE`. FXSR and
+/// XSAVE are needed for `CR4.OSFXSR` and `CR4.OSXSAVE`. The kernel is built for the x86_64
+/// baseline, which includes SSE and SSE2.
+const REQUIRED: [Feature; 9] = [
+    ("long mode", 0x8000_0001, Register::Edx, 29),
+    ("NX", 0x8000_0001, Register::Edx, 20),
+    ("PAE", 1, Register::Edx, 6),
+    ("PSE", 1, Register::Edx, 3),
+    ("PGE", 1, Register::Edx, 13),
+    ("FXSR", 1, Register::Edx, 24),
+    ("SSE", 1, Register::Edx, 25),
+    ("SSE2", 1, Register::Edx, 26),
+    ("XSAVE", 1, Register::Ecx, 26),
+];
+
+/// 1 GiB pages, used for the kernel with `kernel_huge_pages`
+const PAGE_1G: Feature = ("1 GiB pages", 0x8000_0001, Register::Edx, 26);
+
+/// Check CPUID for a feature
+fn has(feature: &Feature) -> bool {
+    let &(_, leaf, register, bit) = feature;
+    unsafe {
+        // Leaves past the maximum return data from another leaf, so they can not be trusted
+        let max = __cpuid(leaf & 0x8000_0000).eax;
+        if leaf > max {
+            return false;
+        }
+
+        let result = __cpuid(leaf);
+        let value = match register {
+            Register::Ecx => result.ecx,
+            Register::Edx => result.edx,
+        };
+        value & 1 << bit != 0
+    }
+}
+
+/// Get the first required feature the CPU does not have, `None` if it has all of them
+pub fn missing_feature() -> Option<&'static str> {
+    REQUIRED.iter().find(|feature| !has(feature)).map(|&(name, ..)| name)
+}
+
+/// Check for 1 GiB page support
+pub fn has_1g_pages() -> bool {
+    has(&PAGE_1G)
+}
@@ -1,9 +1,16 @@
-use core::{mem, ptr};
+use core::{cmp, mem, ptr};
+use std::vec::Vec;
 use uefi::memory::{MemoryDescriptor, MemoryType};
+use uefi::status::Result;
+
+use super::{allocate_zero_pages, serial};
 
 static MM_BASE: u64 = 0x500;
 static MM_SIZE: u64 = 0x4B00;
 
+/// Where the memory map areas are written, `MM_BASE` unless that is not usable
+static mut MM_PHYS: u64 = MM_BASE;
+
 /// Memory does not exist
 pub const MEMORY_AREA_NULL: u32 = 0;
 
@@ -26,11 +33,10 @@ pub struct MemoryArea {
     pub acpi: u32
 }
 
-pub unsafe fn memory_map() -> usize {
+/// Call `f` with each descriptor in the firmware memory map, returning the map key
+unsafe fn for_each_descriptor<F: FnMut(usize, &MemoryDescriptor)>(mut f: F) -> usize {
     let uefi = std::system_table();
 
-    ptr::write_bytes(MM_BASE as *mut u8, 0, MM_SIZE as usize);
-
     let mut map: [u8; 65536] = [0; 65536];
     let mut map_size = map.len();
     let mut map_key = 0;
@@ -47,30 +53,7 @@ pub unsafe fn memory_map() -> usize {
     if descriptor_size >= mem::size_of::<MemoryDescriptor>() {
         for i in 0..map_size/descriptor_size {
             let descriptor_ptr = map.as_ptr().offset((i * descriptor_size) as isize);
-            let descriptor = & *(descriptor_ptr as *const MemoryDescriptor);
-            let descriptor_type: MemoryType = mem::transmute(descriptor.Type);
-
-            let bios_type = match descriptor_type {
-                MemoryType::EfiLoaderCode |
-                MemoryType::EfiLoaderData |
-                MemoryType::EfiBootServicesCode |
-                MemoryType::EfiBootServicesData |
-                MemoryType::EfiConventionalMemory => {
-                    MEMORY_AREA_FREE
-                },
-                _ => {
-                    MEMORY_AREA_RESERVED
-                }
-            };
-
-            let bios_area = MemoryArea {
-                base_addr: descriptor.PhysicalStart.0,
-                length: descriptor.NumberOfPages * 4096,
-                _type: bios_type,
-                acpi: 0,
-            };
-
-            ptr::write((MM_BASE as *mut MemoryArea).offset(i as isize), bios_area);
+            f(i, & *(descriptor_ptr as *const MemoryDescriptor));
         }
     } else {
         println!("Unknown memory descriptor size: {}", descriptor_size);
@@ -78,3 +61,158 @@ pub unsafe fn memory_map() -> usize {
 
     map_key
 }
+
+/// Check that `[base, base + size)` lies within a single free conventional memory region
+pub fn is_conventional(base: u64, size: u64) -> bool {
+    let mut found = false;
+    unsafe {
+        for_each_descriptor(|_i, descriptor| {
+            let descriptor_type: MemoryType = mem::transmute(descriptor.Type);
+            let start = descriptor.PhysicalStart.0;
+            let end = start + descriptor.NumberOfPages * 4096;
+            if let MemoryType::EfiConventionalMemory = descriptor_type {
+                if base >= start && base.saturating_add(size) <= end {
+                    found = true;
+                }
+            }
+        });
+    }
+    found
+}
+
+/// Get the base and page count of every free conventional memory region
+pub fn conventional_ranges() -> Vec<(u64, u64)> {
+    let mut ranges = Vec::new();
+    unsafe {
+        for_each_descriptor(|_i, descriptor| {
+            let descriptor_type: MemoryType = mem::transmute(descriptor.Type);
+            if let MemoryType::EfiConventionalMemory = descriptor_type {
+                ranges.push((descriptor.PhysicalStart.0, descriptor.NumberOfPages));
+            }
+        });
+    }
+    ranges
+}
+
+/// Check if memory of this type may be used once boot services have exited
+fn is_free_type(descriptor_type: MemoryType) -> bool {
+    match descriptor_type {
+        MemoryType::EfiLoaderCode |
+        MemoryType::EfiLoaderData |
+        MemoryType::EfiBootServicesCode |
+        MemoryType::EfiBootServicesData |
+        MemoryType::EfiConventionalMemory => true,
+        _ => false,
+    }
+}
+
+/// Check that all of `[base, base + size)` is memory that is free after boot services exit
+fn is_usable(base: u64, size: u64) -> bool {
+    let end = base.saturating_add(size);
+    let mut covered = 0;
+    unsafe {
+        for_each_descriptor(|_i, descriptor| {
+            let descriptor_type: MemoryType = mem::transmute(descriptor.Type);
+            let start = descriptor.PhysicalStart.0;
+            let descriptor_end = start + descriptor.NumberOfPages * 4096;
+            if is_free_type(descriptor_type) && start < end && descriptor_end > base {
+                covered += cmp::min(end, descriptor_end) - cmp::max(base, start);
+            }
+        });
+    }
+    covered >= size
+}
+
+/// Move the memory map areas out of low memory if the firmware reserved it
+pub fn relocate_if_reserved(page_size: usize) -> Result<()> {
+    if is_usable(MM_BASE, MM_SIZE) {
+        return Ok(());
+    }
+
+    let phys = unsafe { allocate_zero_pages((MM_SIZE as usize + page_size - 1) / page_size)? } as u64;
+    info!("Memory map area at {:X} is reserved by the firmware, relocated to {:X}", MM_BASE, phys);
+    unsafe { MM_PHYS = phys; }
+    Ok(())
+}
+
+/// Base and size of the memory map areas passed to the kernel
+pub fn area() -> (u64, u64) {
+    (unsafe { MM_PHYS }, MM_SIZE)
+}
+
+/// Count the pages of each firmware memory type, indexed by the raw type
+pub fn pages_by_type() -> [u64; 16] {
+    let mut pages = [0; 16];
+    unsafe {
+        for_each_descriptor(|_i, descriptor| {
+            if let Some(count) = pages.get_mut(descriptor.Type as usize) {
+                *count += descriptor.NumberOfPages;
+            }
+        });
+    }
+    pages
+}
+
+/// A firmware memory descriptor, kept so it can be printed after boot services exit
+#[derive(Clone, Copy)]
+struct SavedDescriptor {
+    ty: u32,
+    start: u64,
+    pages: u64,
+    attribute: u64,
+}
+
+const MAX_SAVED: usize = 1024;
+
+static mut SAVED: [SavedDescriptor; MAX_SAVED] = [SavedDescriptor { ty: 0, start: 0, pages: 0, attribute: 0 }; MAX_SAVED];
+static mut SAVED_COUNT: usize = 0;
+
+/// Print the final memory map to the serial port. This does not allocate, so it can be
+/// used after boot services have exited.
+pub unsafe fn dump_serial() {
+    serial::write_str("Type             PhysicalStart    NumberOfPages    Attribute\n");
+    for descriptor in SAVED[..SAVED_COUNT].iter() {
+        serial::write_hex(descriptor.ty as u64);
+        serial::write_str(" ");
+        serial::write_hex(descriptor.start);
+        serial::write_str(" ");
+        serial::write_hex(descriptor.pages);
+        serial::write_str(" ");
+        serial::write_hex(descriptor.attribute);
+        serial::write_str("\n");
+    }
+}
+
+pub unsafe fn memory_map() -> usize {
+    ptr::write_bytes(MM_PHYS as *mut u8, 0, MM_SIZE as usize);
+
+    SAVED_COUNT = 0;
+    for_each_descriptor(|i, descriptor| {
+        if let Some(saved) = SAVED.get_mut(i) {
+            *saved = SavedDescriptor {
+                ty: descriptor.Type,
+                start: descriptor.PhysicalStart.0,
+                pages: descriptor.NumberOfPages,
+                attribute: descriptor.Attribute,
+            };
+            SAVED_COUNT = i + 1;
+        }
+
+        let descriptor_type: MemoryType = mem::transmute(descriptor.Type);
+
+        let bios_type = if is_free_type(descriptor_type) {
+            MEMORY_AREA_FREE
+        } else {
+            MEMORY_AREA_RESERVED
+        };
+
+        let bios_area = MemoryArea {
+            base_addr: descriptor.PhysicalStart.0,
+            length: descriptor.NumberOfPages * 4096,
+            _type: bios_type,
+            acpi: 0,
+        };
+
+        ptr::write((MM_PHYS as *mut MemoryArea).offset(i as isize), bios_area);
+    })
+}
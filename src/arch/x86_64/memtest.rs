@@ -0,0 +1,100 @@
+//! A quick pattern test of free memory, to find bad RAM before the kernel trusts it
+
+use core::ops::Try;
+use core::{ptr, slice};
+use uefi::memory::MemoryType;
+
+use crate::watchdog;
+
+use super::memory_map;
+
+/// Patterns written to every word, then read back. Zero stands for the word's own
+/// address, which catches address lines that alias each other.
+const PATTERNS: [u64; 3] = [0x5555_5555_5555_5555, 0xAAAA_AAAA_AAAA_AAAA, 0];
+
+/// Most failing words that are printed
+const MAX_REPORTED: usize = 16;
+
+/// The value written to `word` for `pattern`
+fn pattern_value(word: &u64, pattern: u64) -> u64 {
+    if pattern == 0 { word as *const u64 as u64 } else { pattern }
+}
+
+/// Test `words` with each pattern, returning the number of failing words
+fn test_words(words: &mut [u64], failures: usize) -> usize {
+    let mut count = 0;
+    for &pattern in PATTERNS.iter() {
+        for word in words.iter_mut() {
+            let value = pattern_value(word, pattern);
+            unsafe { ptr::write_volatile(word, value); }
+        }
+        for word in words.iter() {
+            let value = unsafe { ptr::read_volatile(word) };
+            let expected = pattern_value(word, pattern);
+            if value != expected {
+                if failures + count < MAX_REPORTED {
+                    error!("Memory test failed at {:X}: wrote {:016X}, read {:016X}", word as *const u64 as u64, expected, value);
+                }
+                count += 1;
+            }
+        }
+    }
+    count
+}
+
+/// Test every free conventional memory region, returning the number of failing words
+///
+/// Each region is allocated while it is tested, so the firmware can not hand it out, and
+/// the pages of the bootloader and its heap are never touched.
+pub fn run() -> usize {
+    let uefi = std::system_table();
+
+    let ranges = memory_map::conventional_ranges();
+    let total: u64 = ranges.iter().map(|&(_, pages)| pages).sum();
+    println!("Testing {} MB of memory", total * 4096 / 1024 / 1024);
+
+    // This can take a while on machines with a lot of memory
+    watchdog::suspend();
+
+    let mut failures = 0;
+    let mut done = 0;
+    for &(mut base, mut pages) in ranges.iter() {
+        done += pages;
+        // Page zero can not be used through a Rust reference
+        if base == 0 {
+            base += 4096;
+            pages -= 1;
+        }
+        if pages == 0 {
+            continue;
+        }
+
+        let mut ptr = base as usize;
+        let status = (uefi.BootServices.AllocatePages)(
+            2, // AllocateAddress
+            MemoryType::EfiLoaderData,
+            pages as usize,
+            &mut ptr
+        );
+        if status.branch().is_break() {
+            debug!("Memory test skipped {:X}:{:X}, it was allocated", base, pages * 4096);
+            continue;
+        }
+
+        let words = unsafe { slice::from_raw_parts_mut(ptr as *mut u64, pages as usize * 512) };
+        failures += test_words(words, failures);
+        let _ = (uefi.BootServices.FreePages)(ptr, pages as usize);
+
+        print!("\r{}% - {} MB", done * 100 / total, done * 4096 / 1024 / 1024);
+    }
+    println!("");
+
+    watchdog::resume();
+
+    if failures > 0 {
+        error!("Memory test found {} failing words", failures);
+    } else {
+        info!("Memory test passed");
+    }
+    failures
+}
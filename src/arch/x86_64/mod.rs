@@ -1,6 +1,8 @@
 use core::{cmp, mem, ptr, slice};
-use orbclient::{Color, Renderer};
-use std::fs::find;
+use core::convert::TryFrom;
+use core::ops::Try;
+use std::boxed::Box;
+use std::fs::File;
 use std::proto::Protocol;
 use std::string::String;
 use std::vec::Vec;
@@ -8,47 +10,212 @@ use uefi::status::{Error, Result};
 use uefi::guid::GuidKind;
 use uefi::memory::MemoryType;
 
-use crate::disk::DiskEfi;
-use crate::display::{Display, ScaledDisplay, Output};
-use crate::image::{self, Image};
+use crate::chainload::chainload;
+use crate::compress;
+use crate::config::{config, KernelFormat, KernelSource};
+use crate::device_path::{self, guid_string, DevicePath};
+use crate::disk::{self, DiskEfi, READ_STATS};
+#[cfg(not(feature = "minimal"))]
+use crate::display::Backend;
+use crate::display::Output;
+use crate::elf::{self, PF_X, PT_LOAD};
+use crate::error::{BootError, BootResult};
+use crate::fs::{self, find_file, read_file, BootFs};
+use crate::integrity;
 use crate::key::{key, Key};
-use crate::text::TextDisplay;
+use crate::loaded_image::LoadedImage;
+use crate::proto::{locate_handles, OpenProtocol};
+use crate::rng;
+use crate::secret;
+use crate::time;
+use crate::watchdog;
 
 use self::memory_map::memory_map;
-use self::paging::{paging_create, paging_enter};
+use self::paging::{paging_create, paging_enter, valid_phys_offset, Segment, FRAMEBUFFER_VIRT, IDENTITY_MAP_SIZE, KERNEL_MAP_SIZE, KERNEL_VIRT, PAGE_SIZE_1G, PAGE_SIZE_2M};
 
+mod acpi;
+mod cpu;
 mod memory_map;
+mod memtest;
+mod modules;
 mod paging;
 mod partitions;
+mod rescue;
+pub mod serial;
+mod trampoline;
+#[cfg(not(feature = "minimal"))]
+mod ui;
+mod verbose;
 
-static KERNEL: &'static str = concat!("\\", env!("BASEDIR"), "\\kernel");
-static SPLASHBMP: &'static [u8] = include_bytes!("../../../res/splash.bmp");
-
-static PHYS_OFFSET: u64 = 0xFFFF800000000000;
+static mut PHYS_OFFSET: u64 = 0xFFFF800000000000;
 
 static mut KERNEL_PHYS: u64 = 0;
 static mut KERNEL_SIZE: u64 = 0;
 static mut KERNEL_ENTRY: u64 = 0;
+/// Size of the pages mapping the kernel, see `relocate_huge`
+static mut KERNEL_PAGE_SIZE: u64 = 0x1000;
 
 static mut STACK_PHYS: u64 = 0;
-static STACK_SIZE: u64 = 0x20000;
+static mut STACK_SIZE: u64 = 0;
 
 static mut ENV_PHYS: u64 = 0;
 static mut ENV_SIZE: u64 = 0;
 
-static mut RSDPS_AREA: Option<Vec<u8>> = None;
+static mut RSDPS_PHYS: u64 = 0;
+static mut RSDPS_SIZE: u64 = 0;
+
+static mut ACPI_TABLES_PHYS: u64 = 0;
+static mut ACPI_TABLES_SIZE: u64 = 0;
+
+static mut HARDWARE_REDUCED_ACPI: bool = false;
+
+static mut VERSION_PHYS: u64 = 0;
+static mut VERSION_SIZE: u64 = 0;
+
+static mut RNG_SEED_PHYS: u64 = 0;
+static mut RNG_SEED_QUALITY: u8 = 0;
+
+static mut BOOTLOADER_BASE: u64 = 0;
+static mut BOOTLOADER_SIZE: u64 = 0;
+
+static mut MODULES_PHYS: u64 = 0;
+static mut MODULES_COUNT: u64 = 0;
+
+static mut BOOT_DISK_GUID: [u8; 16] = [0; 16];
+static mut BOOT_PARTITION_ATTRS: u64 = 0;
+
+static mut TSC_FREQ: u64 = 0;
+
+/// Base and size of the framebuffer, mapped at `FRAMEBUFFER_VIRT`
+static mut FRAMEBUFFER: Option<(u64, u64)> = None;
+static mut FRAMEBUFFER_WC: bool = false;
+
+static mut CMDLINE: Option<String> = None;
+
+static mut DUMP_MEMORY_MAP: bool = false;
 
+/// `time::elapsed_ms` when the splash was first drawn, for `splash_min_ms`
+static mut SPLASH_SHOWN_MS: Option<u64> = None;
+
+static mut KERNEL_SEGMENTS: Vec<Segment> = Vec::new();
+
+/// Identifies `KernelArgs`, the bytes `RedoxArg` in little endian
+pub const KERNEL_ARGS_MAGIC: u64 = 0x6772_4178_6F64_6552;
+
+/// Layout version of `KernelArgs`, bump this whenever fields are added
+pub const KERNEL_ARGS_VERSION: u32 = 14;
+
+/// Name and type of the kernel ELF note holding the `KERNEL_ARGS_VERSION` it expects, as a
+/// little endian u32
+const ABI_NOTE_NAME: &[u8] = b"Redox";
+const ABI_NOTE_TYPE: u32 = 1;
+
+/// `KernelArgs.boot_mode` when booted by this bootloader, the BIOS bootloader uses 0
+pub const BOOT_MODE_UEFI: u8 = 1;
+
+/// Arguments passed to the kernel entry point. Offsets are in bytes from the start.
 #[repr(packed)]
 pub struct KernelArgs {
+    /// 0x00: `KERNEL_ARGS_MAGIC`
+    magic: u64,
+    /// 0x08: `KERNEL_ARGS_VERSION`
+    version: u32,
+    /// 0x0C: size of this structure
+    size: u32,
+
+    /// 0x10
     kernel_base: u64,
+    /// 0x18
     kernel_size: u64,
+    /// 0x20
     stack_base: u64,
+    /// 0x28
     stack_size: u64,
+    /// 0x30
     env_base: u64,
+    /// 0x38
     env_size: u64,
 
+    /// 0x40
     acpi_rsdps_base: u64,
+    /// 0x48
     acpi_rsdps_size: u64,
+
+    /// 0x50
+    boot_start_ticks: u64,
+
+    /// 0x58
+    bootloader_version_base: u64,
+    /// 0x60
+    bootloader_version_size: u64,
+
+    /// 0x68: GPT GUID of the partition the kernel was loaded from, zero if unknown
+    boot_disk_guid: [u8; 16],
+
+    /// 0x78
+    tsc_freq_hz: u64,
+
+    /// 0x80: virtual address where physical memory is mapped
+    phys_offset: u64,
+
+    /// 0x88: `BOOT_MODE_UEFI`, or 0 from the BIOS bootloader. Only with UEFI are runtime
+    /// services available, and do the `FRAMEBUFFER_*` env variables describe a GOP framebuffer.
+    boot_mode: u8,
+
+    /// 0x89: table of `modules::ModuleDescriptor` followed by the module names, zero if none
+    modules_base: u64,
+    /// 0x91: number of descriptors in the module table
+    modules_count: u64,
+
+    /// 0x99: copies of the ACPI tables with an index, see `acpi::TableEntry`, zero if not copied
+    acpi_tables_base: u64,
+    /// 0xA1
+    acpi_tables_size: u64,
+
+    /// 0xA9: the `memory_map::MemoryArea` list, at 0x500 unless the firmware reserved that
+    areas_base: u64,
+    /// 0xB1
+    areas_size: u64,
+
+    /// 0xB9: 1 if the FADT marks the platform as ACPI hardware-reduced, otherwise 0
+    hardware_reduced_acpi: u8,
+
+    /// 0xBA: where the firmware loaded this bootloader, for symbolicating faults during
+    /// early boot. This is boot services code memory, which the kernel may reclaim.
+    bootloader_base: u64,
+    /// 0xC2
+    bootloader_size: u64,
+
+    /// 0xCA: GPT attributes of the partition the kernel was loaded from, zero if unknown
+    boot_partition_attrs: u64,
+
+    /// 0xD2: bytes of the kernel read from the boot partition, to judge how the media
+    /// performs. The read stats are zero when the kernel is loaded from the ESP.
+    boot_read_bytes: u64,
+    /// 0xDA: firmware read calls for the kernel, including failed ones
+    boot_read_ops: u64,
+    /// 0xE2: failed read-ahead windows that were read again directly, see `ReadStats`
+    boot_read_retries: u64,
+
+    /// 0xEA: 1 if the framebuffer is mapped write-combining, with PAT entry 4 set to
+    /// write-combining, otherwise 0
+    framebuffer_wc: u8,
+
+    /// 0xEB: virtual address of the framebuffer, mapped by the bootloader so the kernel can
+    /// draw before it has its own page tables, zero if there is no framebuffer
+    framebuffer_virt: u64,
+
+    /// 0xF3: seed for the kernel's random number generator, never all zeroes
+    rng_seed_base: u64,
+    /// 0xFB
+    rng_seed_size: u64,
+    /// 0x103: `rng::QUALITY_*` of the seed, the kernel should gather more entropy before
+    /// relying on a low quality seed
+    rng_seed_quality: u8,
+
+    /// 0x104: size of the pages mapping the kernel, 0x4000_0000 or 0x20_0000 if it was moved
+    /// to a 1 GiB aligned `kernel_base` for huge pages, otherwise 0x1000
+    kernel_page_size: u64,
 }
 
 unsafe fn allocate_zero_pages(pages: usize) -> Result<usize> {
@@ -62,76 +229,447 @@ unsafe fn allocate_zero_pages(pages: usize) -> Result<usize> {
         &mut ptr
     )?;
 
-    ptr::write_bytes(ptr as *mut u8, 0, 4096);
+    ptr::write_bytes(ptr as *mut u8, 0, pages * 4096);
 
     Ok(ptr)
 }
 
-unsafe fn exit_boot_services(key: usize) {
+unsafe fn allocate_zero_pages_at(address: usize, pages: usize) -> Result<usize> {
+    let uefi = std::system_table();
+
+    let mut ptr = address;
+    (uefi.BootServices.AllocatePages)(
+        2, // AllocateAddress
+        MemoryType::EfiRuntimeServicesData, // Keeps this memory out of free space list
+        pages,
+        &mut ptr
+    )?;
+
+    ptr::write_bytes(ptr as *mut u8, 0, pages * 4096);
+
+    Ok(ptr)
+}
+
+unsafe fn free_pages(data: &[u8], page_size: usize) {
+    let uefi = std::system_table();
+
+    let pages = (data.len() + page_size - 1) / page_size;
+    let _ = (uefi.BootServices.FreePages)(data.as_ptr() as usize, pages);
+}
+
+/// Allocate memory for the kernel, at the given load address if there is one
+fn allocate_kernel(len: u64, page_size: usize, load_addr: Option<u64>) -> Result<&'static mut [u8]> {
+    // A corrupt filesystem may report an absurd length, refuse it before allocating
+    if len > config().max_kernel_size {
+        error!("Kernel size {} MB is over the maximum of {} MB", len / MB as u64, config().max_kernel_size / MB as u64);
+        return Err(Error::BadBufferSize);
+    }
+
+    // Refuse lengths that do not fit the address space instead of truncating them
+    let size = usize::try_from(len).map_err(|_| Error::OutOfResources)?;
+    let pages = size.checked_add(page_size - 1).ok_or(Error::OutOfResources)? / page_size;
+
+    let ptr = if let Some(load_addr) = load_addr {
+        if load_addr % page_size as u64 != 0 {
+            error!("Kernel load address {:X} is not page aligned", load_addr);
+            return Err(Error::InvalidParameter);
+        }
+        if !memory_map::is_conventional(load_addr, (pages * page_size) as u64) {
+            error!("Kernel load address {:X} is not in free memory", load_addr);
+            return Err(Error::InvalidParameter);
+        }
+        unsafe { allocate_zero_pages_at(load_addr as usize, pages)? }
+    } else {
+        unsafe { allocate_zero_pages(pages)? }
+    };
+
+    Ok(unsafe {
+        slice::from_raw_parts_mut(
+            ptr as *mut u8,
+            size
+        )
+    })
+}
+
+/// Move the kernel to the first free base in the identity map aligned to its huge page size,
+/// 1 GiB, or 2 MiB without 1 GiB pages, so it can be mapped with huge pages. Returns the
+/// kernel and the size of its pages, which stay 4 KiB if there is no free base.
+fn relocate_huge(kernel: &'static mut [u8], page_size: usize) -> BootResult<(&'static mut [u8], u64)> {
+    let huge_page_size = if cpu::has_1g_pages() {
+        PAGE_SIZE_1G
+    } else {
+        warn!("CPU does not support 1 GiB pages, mapping the kernel with 2 MiB pages");
+        PAGE_SIZE_2M
+    };
+
+    let len = kernel.len() as u64;
+    let size = (len + page_size as u64 - 1) / page_size as u64 * page_size as u64;
+    let base = if kernel.as_ptr() as u64 % huge_page_size == 0 {
+        Some(kernel.as_ptr() as u64)
+    } else {
+        // Zero is never free, the firmware keeps real mode structures there
+        (1..IDENTITY_MAP_SIZE / huge_page_size)
+            .map(|i| i * huge_page_size)
+            .find(|&base| memory_map::is_conventional(base, size))
+    };
+    let base = match base {
+        Some(base) => base,
+        None => {
+            warn!("No free {} MiB aligned memory for the kernel, mapping it with 4 KiB pages", huge_page_size / MB as u64);
+            return Ok((kernel, 0x1000));
+        }
+    };
+
+    if kernel.as_ptr() as u64 == base {
+        return Ok((kernel, huge_page_size));
+    }
+
+    let data = kernel.to_vec();
+    let moved = reallocate_kernel(kernel, &data, len, page_size, Some(base))?;
+    debug!("Moved kernel to {:X} for {:X} byte pages", base, huge_page_size);
+    Ok((moved, huge_page_size))
+}
+
+/// Replace the kernel pages `old` with `len` bytes at `load_addr` that start with `data`.
+/// The old pages are freed first, since they may overlap the load address.
+fn reallocate_kernel(old: &'static mut [u8], data: &[u8], len: u64, page_size: usize, load_addr: Option<u64>) -> BootResult<&'static mut [u8]> {
+    unsafe { free_pages(old, page_size); }
+    let new = allocate_kernel(len, page_size, load_addr)?;
+    copy_kernel(&mut new[..data.len()], data)?;
+    Ok(new)
+}
+
+/// Copy `src` to the kernel memory at `dest`, reading it back with `verify_copy` since some
+/// firmware reports read-only or MMIO ranges as usable memory
+fn copy_kernel(dest: &mut [u8], src: &[u8]) -> BootResult<()> {
+    dest.copy_from_slice(src);
+    if config().verify_copy {
+        for (i, byte) in src.iter().enumerate() {
+            // Volatile so the read is not replaced with the value just written
+            let read = unsafe { ptr::read_volatile(dest.as_ptr().add(i)) };
+            if read != *byte {
+                error!("Kernel copy at {:X} differs at offset {:X}: wrote {:02X}, read {:02X}", dest.as_ptr() as usize, i, byte, read);
+                return Err(BootError::CopyMismatch(i));
+            }
+        }
+        debug!("Verified kernel copy of {:X} bytes", src.len());
+    }
+    Ok(())
+}
+
+/// Read a chunk of the kernel with `read`, going through `bounce` with `verify_copy` so the
+/// chunk is read back from kernel memory like `copy_kernel`
+fn read_kernel_chunk<F: FnMut(&mut [u8]) -> Result<usize>>(chunk: &mut [u8], bounce: &mut Vec<u8>, mut read: F) -> BootResult<usize> {
+    if !config().verify_copy {
+        return Ok(read(chunk)?);
+    }
+    bounce.resize(chunk.len(), 0);
+    let count = cmp::min(read(bounce)?, chunk.len());
+    copy_kernel(&mut chunk[..count], &bounce[..count])?;
+    Ok(count)
+}
+
+/// Make sure a region will be reachable through the identity map once paging is set up
+fn check_identity_mapped(name: &str, base: u64, size: u64) -> Result<()> {
+    if base.saturating_add(size) > IDENTITY_MAP_SIZE {
+        error!("{} at {:X}:{:X} is outside of the {} GiB identity map", name, base, size, IDENTITY_MAP_SIZE / 0x4000_0000);
+        return Err(Error::OutOfResources);
+    }
+    Ok(())
+}
+
+/// Times to fetch the memory map and call ExitBootServices before giving up
+const EXIT_BOOT_SERVICES_ATTEMPTS: usize = 4;
+
+/// Exit boot services, fetching the memory map again when the firmware rejects the key.
+/// The key goes stale if anything allocates after the map was fetched, including the
+/// firmware itself, so nothing may allocate or print between attempts.
+unsafe fn exit_boot_services() {
     let handle = std::handle();
     let uefi = std::system_table();
 
-    let _ = (uefi.BootServices.ExitBootServices)(handle, key);
+    for _ in 0..EXIT_BOOT_SERVICES_ATTEMPTS {
+        let key = memory_map();
+        if (uefi.BootServices.ExitBootServices)(handle, key).branch().is_continue() {
+            return;
+        }
+    }
+
+    // Only GetMemoryMap may be called after a failed ExitBootServices, so the console
+    // cannot be used anymore
+    serial::init();
+    serial::write_str("ExitBootServices failed, halting\n");
+    loop {
+        llvm_asm!("cli; hlt" : : : "memory" : "intel", "volatile");
+    }
 }
 
-unsafe fn enter() -> ! {
-    let args = KernelArgs {
+unsafe fn kernel_args() -> KernelArgs {
+    KernelArgs {
+        magic: KERNEL_ARGS_MAGIC,
+        version: KERNEL_ARGS_VERSION,
+        size: mem::size_of::<KernelArgs>() as u32,
         kernel_base: KERNEL_PHYS,
         kernel_size: KERNEL_SIZE,
         stack_base: STACK_PHYS,
         stack_size: STACK_SIZE,
         env_base: ENV_PHYS,
         env_size: ENV_SIZE,
-        acpi_rsdps_base: RSDPS_AREA.as_ref().map(Vec::as_ptr).unwrap_or(core::ptr::null()) as usize as u64 + PHYS_OFFSET,
-        acpi_rsdps_size: RSDPS_AREA.as_ref().map(Vec::len).unwrap_or(0) as u64,
-    };
+        acpi_rsdps_base: RSDPS_PHYS + PHYS_OFFSET,
+        acpi_rsdps_size: RSDPS_SIZE,
+        boot_start_ticks: time::start_ticks(),
+        bootloader_version_base: VERSION_PHYS,
+        bootloader_version_size: VERSION_SIZE,
+        boot_disk_guid: BOOT_DISK_GUID,
+        tsc_freq_hz: TSC_FREQ,
+        phys_offset: PHYS_OFFSET,
+        boot_mode: BOOT_MODE_UEFI,
+        modules_base: MODULES_PHYS,
+        modules_count: MODULES_COUNT,
+        acpi_tables_base: ACPI_TABLES_PHYS,
+        acpi_tables_size: ACPI_TABLES_SIZE,
+        areas_base: memory_map::area().0,
+        areas_size: memory_map::area().1,
+        hardware_reduced_acpi: HARDWARE_REDUCED_ACPI as u8,
+        bootloader_base: BOOTLOADER_BASE,
+        bootloader_size: BOOTLOADER_SIZE,
+        boot_partition_attrs: BOOT_PARTITION_ATTRS,
+        boot_read_bytes: READ_STATS.bytes,
+        boot_read_ops: READ_STATS.ops,
+        boot_read_retries: READ_STATS.retries,
+        framebuffer_wc: (FRAMEBUFFER_WC && FRAMEBUFFER.is_some()) as u8,
+        framebuffer_virt: FRAMEBUFFER.map_or(0, |(base, _)| FRAMEBUFFER_VIRT + base % 0x1000),
+        rng_seed_base: RNG_SEED_PHYS,
+        rng_seed_size: rng::SEED_SIZE as u64,
+        rng_seed_quality: RNG_SEED_QUALITY,
+        kernel_page_size: KERNEL_PAGE_SIZE,
+    }
+}
 
-    let entry_fn: extern "sysv64" fn(args_ptr: *const KernelArgs) -> ! = mem::transmute(KERNEL_ENTRY);
-    entry_fn(&args);
+/// Print every field of the kernel arguments
+fn dump_kernel_args(args: &KernelArgs) {
+    // Fields of a packed struct may be unaligned, so copy them before formatting
+    let fields: [(&str, u64); 36] = [
+        ("magic", args.magic),
+        ("version", args.version as u64),
+        ("size", args.size as u64),
+        ("kernel_base", args.kernel_base),
+        ("kernel_size", args.kernel_size),
+        ("stack_base", args.stack_base),
+        ("stack_size", args.stack_size),
+        ("env_base", args.env_base),
+        ("env_size", args.env_size),
+        ("acpi_rsdps_base", args.acpi_rsdps_base),
+        ("acpi_rsdps_size", args.acpi_rsdps_size),
+        ("boot_start_ticks", args.boot_start_ticks),
+        ("bootloader_version_base", args.bootloader_version_base),
+        ("bootloader_version_size", args.bootloader_version_size),
+        ("tsc_freq_hz", args.tsc_freq_hz),
+        ("phys_offset", args.phys_offset),
+        ("boot_mode", args.boot_mode as u64),
+        ("modules_base", args.modules_base),
+        ("modules_count", args.modules_count),
+        ("acpi_tables_base", args.acpi_tables_base),
+        ("acpi_tables_size", args.acpi_tables_size),
+        ("areas_base", args.areas_base),
+        ("areas_size", args.areas_size),
+        ("hardware_reduced_acpi", args.hardware_reduced_acpi as u64),
+        ("bootloader_base", args.bootloader_base),
+        ("bootloader_size", args.bootloader_size),
+        ("boot_partition_attrs", args.boot_partition_attrs),
+        ("boot_read_bytes", args.boot_read_bytes),
+        ("boot_read_ops", args.boot_read_ops),
+        ("boot_read_retries", args.boot_read_retries),
+        ("framebuffer_wc", args.framebuffer_wc as u64),
+        ("framebuffer_virt", args.framebuffer_virt),
+        ("rng_seed_base", args.rng_seed_base),
+        ("rng_seed_size", args.rng_seed_size),
+        ("rng_seed_quality", args.rng_seed_quality as u64),
+        ("kernel_page_size", args.kernel_page_size),
+    ];
+
+    println!("KernelArgs:");
+    for (name, value) in fields.iter() {
+        println!("  {:<24} {:016X}", name, value);
+    }
+    println!("  {:<24} {}", "boot_disk_guid", guid_string(&{args.boot_disk_guid}));
 }
 
-fn get_correct_block_io() -> Result<DiskEfi> {
-    // Get all BlockIo handles.
-    let mut handles = vec! [uefi::Handle(0); 128];
-    let mut size = handles.len() * mem::size_of::<uefi::Handle>();
+/// Halt with a message on the serial port, for failures after boot services have exited
+unsafe fn self_test_fail(message: &str, value: u64) -> ! {
+    serial::write_str("Self-test failed: ");
+    serial::write_str(message);
+    serial::write_str(" ");
+    serial::write_hex(value);
+    serial::write_str("\n");
+    loop {
+        llvm_asm!("cli; hlt" : : : "memory" : "intel", "volatile");
+    }
+}
 
-    (std::system_table().BootServices.LocateHandle)(uefi::boot::LocateSearchType::ByProtocol, &uefi::guid::BLOCK_IO_GUID, 0, &mut size, handles.as_mut_ptr())?;
+/// Check that the kernel memory is usable and the entry point is inside of the kernel mapping.
+/// This runs after boot services have exited, so it only reports through the serial port.
+unsafe fn self_test() {
+    const PATTERN: u64 = 0x5A5A_A5A5_0F0F_F0F0;
+
+    serial::init();
+
+    // The kernel is already loaded here, so restore what was overwritten
+    let base = KERNEL_PHYS as *mut u64;
+    let original = ptr::read_volatile(base);
+    ptr::write_volatile(base, PATTERN);
+    let readback = ptr::read_volatile(base);
+    ptr::write_volatile(base, original);
+    if readback != PATTERN {
+        self_test_fail("kernel memory readback", readback);
+    }
 
-    let max_size = size / mem::size_of::<uefi::Handle>();
-    let actual_size = std::cmp::min(handles.len(), max_size);
+    if KERNEL_ENTRY < KERNEL_VIRT || KERNEL_ENTRY >= KERNEL_VIRT + KERNEL_SIZE {
+        self_test_fail("entry point outside of kernel", KERNEL_ENTRY);
+    }
 
-    // Return the handle that seems bootable.
-    for handle in handles.into_iter().take(actual_size) {
-        let block_io = DiskEfi::handle_protocol(handle)?;
+    serial::write_str("Self-test passed\n");
+}
+
+/// Write the kernel args to the top of the stack, then jump to the kernel through the trampoline
+unsafe fn enter(page_phys: u64) -> ! {
+    let args_phys = (STACK_PHYS + STACK_SIZE - mem::size_of::<KernelArgs>() as u64) & !0xF;
+    ptr::write(args_phys as *mut KernelArgs, kernel_args());
+
+    // The stack and args are used through the physical offset mapping, like the kernel expects
+    trampoline::jump(page_phys, args_phys + PHYS_OFFSET, KERNEL_ENTRY, args_phys + PHYS_OFFSET);
+}
+
+/// Open partition `partition` of disk `disk`, both counted from zero in `sort_handles`
+/// order, or `None` if either is out of range
+fn fixed_block_io(disk: usize, partition: usize) -> BootResult<Option<DiskEfi>> {
+    let mut handles = locate_handles(&uefi::guid::BLOCK_IO_GUID)?;
+    device_path::sort_handles(&mut handles);
+
+    let is_partition = |handle| OpenProtocol::<DiskEfi>::open(handle)
+        .map(|block_io| block_io.0.Media.LogicalPartition)
+        .ok();
+    let disk_handle = match handles.iter().filter(|&&handle| is_partition(handle) == Some(false)).nth(disk) {
+        Some(&handle) => handle,
+        None => return Ok(None),
+    };
+    let disk_path = DevicePath::handle_protocol(disk_handle)?;
+
+    let handle = handles.iter().filter(|&&handle| {
+        is_partition(handle) == Some(true) && DevicePath::handle_protocol(handle)
+            .map(|path| device_path::is_below(&disk_path, &path))
+            .unwrap_or(false)
+    }).nth(partition);
+    let handle = match handle {
+        Some(&handle) => handle,
+        None => return Ok(None),
+    };
+
+    let path = DevicePath::handle_protocol(handle)?;
+    if let Some(guid) = path.iter().filter_map(|node| device_path::partition_guid(&node)).last() {
+        unsafe { BOOT_DISK_GUID = guid; }
+    }
+    info!("Using partition {} of disk {} at handle {:X}", partition, disk, handle.0);
+    Ok(Some(OpenProtocol::<DiskEfi>::open(handle)?.leak()))
+}
+
+/// Find the boot partition, and the filesystem block inside of it if it is not at the start
+fn get_correct_block_io() -> BootResult<(DiskEfi, Option<u64>)> {
+    if config().boot_disk.is_some() || config().boot_partition.is_some() {
+        let disk = config().boot_disk.unwrap_or(0);
+        let partition = config().boot_partition.unwrap_or(0);
+        match fixed_block_io(disk, partition)? {
+            Some(block_io) => return Ok((block_io, None)),
+            None => warn!("There is no partition {} of disk {}, scanning for the boot partition", partition, disk),
+        }
+    }
+
+    // Get all BlockIo handles, in a stable order so the same partition is picked every boot
+    let mut handles = locate_handles(&uefi::guid::BLOCK_IO_GUID)?;
+    device_path::sort_handles(&mut handles);
+
+    // Return the handle that seems bootable, preferring GPT partitions flagged bootable.
+    // Both protocols are closed again for partitions that are skipped
+    let mut fallback = None;
+    for handle in handles {
+        let mut block_io = OpenProtocol::<DiskEfi>::open(handle)?;
         if !block_io.0.Media.LogicalPartition {
             continue;
         }
 
-        let part = partitions::PartitionProto::handle_protocol(handle)?.0;
+        let part_proto = OpenProtocol::<partitions::PartitionProto>::open(handle)?;
+        let part = &part_proto.0;
         if part.sys == 1 {
             continue;
         }
-        assert_eq!({part.rev}, partitions::PARTITION_INFO_PROTOCOL_REVISION);
+        if { part.rev } != partitions::PARTITION_INFO_PROTOCOL_REVISION {
+            warn!("Skipping handle {:X} with partition info revision {:X}", handle.0, { part.rev });
+            continue;
+        }
         if part.ty == partitions::PartitionProtoDataTy::Gpt as u32 {
             let gpt = unsafe { part.info.gpt };
-            assert_ne!(gpt.part_ty_guid, partitions::ESP_GUID, "detected esp partition again");
+            if gpt.part_ty_guid == partitions::ESP_GUID {
+                // Dual boot systems may have more than one ESP
+                info!("Skipping ESP at handle {:X}", handle.0);
+                continue;
+            }
             if gpt.part_ty_guid == partitions::REDOX_FS_GUID || gpt.part_ty_guid == partitions::LINUX_FS_GUID {
-                return Ok(block_io);
+                let attrs = gpt.attrs;
+                if attrs & (partitions::GPT_ATTR_REQUIRED | partitions::GPT_ATTR_NO_BLOCK_IO) != 0 {
+                    info!("Skipping partition at handle {:X} with attributes {:X}", handle.0, attrs);
+                    continue;
+                }
+                if attrs & partitions::GPT_ATTR_READ_ONLY != 0 {
+                    info!("Partition at handle {:X} is read-only", handle.0);
+                }
+                if attrs & partitions::GPT_ATTR_LEGACY_BOOTABLE != 0 {
+                    unsafe {
+                        BOOT_DISK_GUID = gpt.uniq_guid;
+                        BOOT_PARTITION_ATTRS = attrs;
+                    }
+                    return Ok((block_io.leak(), None));
+                }
+                if fallback.is_none() {
+                    fallback = Some((block_io, gpt.uniq_guid, attrs));
+                }
+                continue;
             }
         } else if part.ty == partitions::PartitionProtoDataTy::Mbr as u32 {
             let mbr = unsafe { part.info.mbr };
             if mbr.ty == 0x83 {
-                return Ok(block_io);
+                return Ok((block_io.leak(), None));
+            }
+            if partitions::MBR_EXTENDED_TYPES.contains(&mbr.ty) {
+                // Firmware may not create handles for logical partitions
+                if let Some(lba) = partitions::find_logical(&mut block_io, 0x83) {
+                    let offset = lba * block_io.0.Media.BlockSize as u64;
+                    if offset % redoxfs::BLOCK_SIZE == 0 {
+                        info!("Found logical partition at LBA {:X} of handle {:X}", lba, handle.0);
+                        return Ok((block_io.leak(), Some(offset / redoxfs::BLOCK_SIZE)));
+                    }
+                    warn!("Logical partition at LBA {:X} is not aligned to the filesystem block size", lba);
+                }
             }
         } else {
             continue;
         }
     }
-    panic!("Couldn't find handle for partition");
+
+    if let Some((block_io, guid, attrs)) = fallback {
+        unsafe {
+            BOOT_DISK_GUID = guid;
+            BOOT_PARTITION_ATTRS = attrs;
+        }
+        return Ok((block_io.leak(), None));
+    }
+    Err(BootError::NoBootPartition)
 }
 
 struct Invalid;
 
+/// Upper bound for the length an RSDP may report, real ones are 36 bytes
+const MAX_RSDP_LENGTH: usize = 4096;
+
 fn validate_rsdp(address: usize, v2: bool) -> core::result::Result<usize, Invalid> {
     #[repr(packed)]
     #[derive(Clone, Copy, Debug)]
@@ -151,7 +689,7 @@ fn validate_rsdp(address: usize, v2: bool) -> core::result::Result<usize, Invali
     let rsdp_bytes = unsafe { core::slice::from_raw_parts(address as *const u8, core::mem::size_of::<Rsdp>()) };
     let rsdp = unsafe { (rsdp_bytes.as_ptr() as *const Rsdp).as_ref::<'static>().unwrap() };
 
-    println!("RSDP: {:?}", rsdp);
+    debug!("RSDP: {:?}", rsdp);
 
     if rsdp.signature != *b"RSD PTR " {
         return Err(Invalid);
@@ -177,44 +715,351 @@ fn validate_rsdp(address: usize, v2: bool) -> core::result::Result<usize, Invali
 
     let length = if rsdp.revision == 2 { rsdp.length as usize } else { core::mem::size_of::<Rsdp>() };
 
+    // The length is copied straight from the table, so a bogus value must not make
+    // the caller read far past the RSDP
+    if length < core::mem::size_of::<Rsdp>() || length > MAX_RSDP_LENGTH {
+        error!("RSDP length {} is out of range", length);
+        return Err(Invalid);
+    }
+
     Ok(length)
 }
 
-fn find_acpi_table_pointers() -> Result<()> {
-    let rsdps_area = unsafe {
-        RSDPS_AREA = Some(Vec::new());
-        RSDPS_AREA.as_mut().unwrap()
-    };
+fn find_acpi_table_pointers(page_size: usize) -> Result<()> {
+    let mut rsdps_area = Vec::new();
 
     let cfg_tables = std::system_table().config_tables();
 
-    for (address, v2) in cfg_tables.iter().find_map(|cfg_table| if cfg_table.VendorGuid.kind() == GuidKind::Acpi { Some((cfg_table.VendorTable, false)) } else if cfg_table.VendorGuid.kind() == GuidKind::Acpi2 { Some((cfg_table.VendorTable, true)) } else { None }) {
+    let mut rsdps: Vec<(usize, bool)> = cfg_tables.iter().filter_map(|cfg_table| if cfg_table.VendorGuid.kind() == GuidKind::Acpi { Some((cfg_table.VendorTable, false)) } else if cfg_table.VendorGuid.kind() == GuidKind::Acpi2 { Some((cfg_table.VendorTable, true)) } else { None }).collect();
+
+    // Put ACPI 2.0 RSDPs first so the kernel sees them before 1.0 ones, and drop
+    // entries for the same RSDP, keeping the 2.0 one
+    rsdps.sort_by_key(|&(_address, v2)| !v2);
+    let mut seen = Vec::new();
+    rsdps.retain(|&(address, _v2)| if seen.contains(&address) {
+        false
+    } else {
+        seen.push(address);
+        true
+    });
+
+    // The first valid RSDP, preferring ACPI 2.0, is used to find the other tables
+    let mut root = None;
+    for (address, v2) in rsdps {
         match validate_rsdp(address, v2) {
             Ok(length) => {
+                if root.is_none() {
+                    root = Some(address);
+                }
+
                 let align = 8;
 
                 rsdps_area.extend(&u32::to_ne_bytes(length as u32));
                 rsdps_area.extend(unsafe { core::slice::from_raw_parts(address as *const u8, length) });
                 rsdps_area.resize(((rsdps_area.len() + (align - 1)) / align) * align, 0u8);
             }
-            Err(_) => println!("Found RSDP that wasn't valid at {:p}", address as *const u8),
+            Err(_) => warn!("Found RSDP that wasn't valid at {:p}", address as *const u8),
+        }
+    }
+
+    // The heap is boot services memory that the kernel may reclaim, so copy the
+    // RSDPs to pages that are kept out of the free space list
+    if !rsdps_area.is_empty() {
+        unsafe {
+            RSDPS_PHYS = allocate_zero_pages((rsdps_area.len() + page_size - 1) / page_size)? as u64;
+            RSDPS_SIZE = rsdps_area.len() as u64;
+            ptr::copy(rsdps_area.as_ptr(), RSDPS_PHYS as *mut u8, rsdps_area.len());
+        }
+    }
+
+    if let Some(address) = root {
+        let (rsdt_addr, xsdt_addr) = unsafe {
+            let revision = ptr::read((address + 15) as *const u8);
+            let rsdt_addr = ptr::read_unaligned((address + 16) as *const u32);
+            // Only ACPI 2.0 RSDPs have an XSDT address
+            let xsdt_addr = if revision >= 2 {
+                ptr::read_unaligned((address + 24) as *const u64)
+            } else {
+                0
+            };
+            (rsdt_addr, xsdt_addr)
+        };
+
+        match acpi::hardware_reduced(rsdt_addr, xsdt_addr) {
+            Some(hardware_reduced) => {
+                info!("ACPI hardware-reduced: {}", hardware_reduced);
+                unsafe { HARDWARE_REDUCED_ACPI = hardware_reduced; }
+            },
+            None => warn!("No valid FADT found"),
+        }
+
+        // Overrides only reach the kernel through the copied tables
+        if config().acpi_copy_tables || config().acpi_override {
+            let overrides = if config().acpi_override { acpi::load_overrides() } else { Vec::new() };
+            let (base, size) = acpi::copy_tables(rsdt_addr, xsdt_addr, &overrides, page_size)?;
+            unsafe {
+                ACPI_TABLES_PHYS = base;
+                ACPI_TABLES_SIZE = size;
+            }
+            check_identity_mapped("ACPI tables", base, size)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "net")]
+fn fetch_kernel(url: &str) -> Result<Vec<u8>> {
+    crate::net::fetch(url)
+}
+
+#[cfg(not(feature = "net"))]
+fn fetch_kernel(url: &str) -> Result<Vec<u8>> {
+    error!("Cannot fetch {}, network support was not enabled in this build", url);
+    Err(Error::Unsupported)
+}
+
+/// Get the loadable segments of the kernel, relative to the kernel mapping
+fn kernel_segments(kernel: &[u8]) -> BootResult<Vec<Segment>> {
+    let mut segments = Vec::new();
+    for header in elf::program_headers(kernel).map_err(|_| BootError::InvalidElf)? {
+        if header.p_type != PT_LOAD {
+            continue;
+        }
+
+        let start = match header.p_vaddr.checked_sub(KERNEL_VIRT) {
+            Some(start) if start.saturating_add(header.p_memsz) <= KERNEL_MAP_SIZE => start,
+            _ => {
+                error!("Kernel segment {:X}:{:X} is outside of the kernel mapping", header.p_vaddr, header.p_memsz);
+                return Err(BootError::InvalidElf);
+            }
+        };
+
+        debug!("Kernel segment {:X}:{:X} flags {:X}", header.p_vaddr, header.p_memsz, header.p_flags);
+        segments.push(Segment {
+            start,
+            end: start + header.p_memsz,
+            flags: header.p_flags,
+        });
+    }
+    Ok(segments)
+}
+
+/// Compare the ABI version in the kernel's note with ours. Kernels without the note predate
+/// it, so only a present and different version is a mismatch.
+fn check_kernel_abi(kernel: &[u8]) -> BootResult<()> {
+    let headers = elf::program_headers(kernel).map_err(|_| BootError::InvalidElf)?;
+    let version = match elf::find_note(kernel, &headers, ABI_NOTE_NAME, ABI_NOTE_TYPE) {
+        Some(desc) if desc.len() >= 4 => u32::from_le_bytes([desc[0], desc[1], desc[2], desc[3]]),
+        _ => {
+            debug!("Kernel has no ABI note");
+            return Ok(());
+        }
+    };
+
+    if version != KERNEL_ARGS_VERSION {
+        warn!("Kernel expects bootloader ABI v{}, this is v{}", version, KERNEL_ARGS_VERSION);
+        if config().abi_strict {
+            return Err(BootError::AbiMismatch(version));
         }
     }
     Ok(())
 }
 
-fn redoxfs() -> Result<redoxfs::FileSystem<DiskEfi>> {
+/// Get the size of the kernel in memory, including the .bss, from its loadable segments
+fn kernel_memory_size(kernel: &[u8]) -> Option<u64> {
+    elf::program_headers(kernel).ok()?
+        .iter()
+        .filter(|header| header.p_type == PT_LOAD && header.p_vaddr >= KERNEL_VIRT)
+        .map(|header| (header.p_vaddr - KERNEL_VIRT).saturating_add(header.p_memsz))
+        .max()
+}
+
+/// Open the kernel on the ESP, unless the configured kernel source skips it
+fn find_esp_kernel() -> BootResult<Option<File>> {
+    let path = config().kernel_esp_path();
+    match config().kernel_source {
+        KernelSource::Auto => Ok(find_file(&path).ok()),
+        KernelSource::Esp => match find_file(&path) {
+            Ok(file) => Ok(Some(file)),
+            Err(_) => {
+                error!("Kernel not found at {}", path);
+                Err(BootError::KernelNotFound)
+            }
+        },
+        KernelSource::Redoxfs => Ok(None),
+    }
+}
+
+/// Open the boot partition and find the configured kernel in it
+fn find_partition_kernel() -> BootResult<(Box<dyn BootFs>, u64)> {
     // TODO: Scan multiple partitions for a kernel.
-    // TODO: pass block_opt for performance reasons
-    redoxfs::FileSystem::open(get_correct_block_io()?, None).map_err(|_| Error::DeviceError)
+    let (disk, block_opt) = get_correct_block_io()?;
+    let mut fs = fs::open(disk, block_opt)?;
+
+    let file = fs.find_path(&config().kernel).map_err(|err| match err {
+        Error::NotFound => BootError::KernelNotFound,
+        err => err.into(),
+    })?;
+    Ok((fs, file))
 }
 
 const MB: usize = 1024 * 1024;
 
-fn inner() -> Result<()> {
+const MIN_STACK_SIZE: usize = 0x10000;
+const MAX_STACK_SIZE: usize = 64 * MB;
+
+/// Text console row used for progress when booting without graphics
+static mut TEXT_PROGRESS_ROW: Option<usize> = None;
+
+fn progress(done: u64, total: u64) {
+    let percent = if total > 0 { done.saturating_mul(100) / total } else { 100 };
+
+    if let Some(row) = unsafe { TEXT_PROGRESS_ROW } {
+        // Draw on the fixed progress line, then return to the log
+        let output = std::system_table().ConsoleOut;
+        let column = output.Mode.CursorColumn as usize;
+        let log_row = output.Mode.CursorRow as usize;
+        let _ = (output.SetCursorPosition)(output, 0, row);
+        print!("Loading kernel: {:>3}% - {} MB", percent, done / MB as u64);
+        let _ = (output.SetCursorPosition)(output, column, log_row);
+    } else {
+        print!("\r{}% - {} MB", percent, done / MB as u64);
+    }
+}
+
+fn progress_done() {
+    if unsafe { TEXT_PROGRESS_ROW }.is_none() {
+        println!("");
+    }
+}
+
+/// Run `f` with a simple text UI, for when no graphics are available
+fn text_pipe<T, F: FnMut() -> Result<T>>(mut f: F) -> Result<T> {
+    let output = std::system_table().ConsoleOut;
+    let _ = (output.ClearScreen)(output);
+
+    println!(
+        "Redox Bootloader {} {}",
+        env!("CARGO_PKG_VERSION"),
+        env!("TARGET").split('-').next().unwrap_or("")
+    );
+    println!("");
+    unsafe { TEXT_PROGRESS_ROW = Some(output.Mode.CursorRow as usize); }
+    println!("");
+    println!("");
+
+    let res = f();
+
+    unsafe { TEXT_PROGRESS_ROW = None; }
+
+    res
+}
+
+/// Options that change how the kernel is loaded
+#[derive(Clone, Copy, Debug)]
+struct BootFlags {
+    safe_mode: bool,
+    load_addr: Option<u64>,
+    fine_grained_paging: bool,
+    huge_pages: bool,
+    verify: bool,
+}
+
+impl BootFlags {
+    /// Use the configuration, or the simplest code paths in safe mode
+    fn new(safe_mode: bool) -> Self {
+        // A flat kernel only runs at the address it was linked for, even in safe mode
+        let flat_load = match config().kernel_format {
+            KernelFormat::Flat => config().flat_load,
+            _ => None,
+        };
+        if safe_mode {
+            Self {
+                safe_mode,
+                load_addr: flat_load,
+                fine_grained_paging: false,
+                huge_pages: false,
+                verify: false,
+            }
+        } else {
+            Self {
+                safe_mode,
+                load_addr: flat_load.or(config().load_addr),
+                fine_grained_paging: config().fine_grained_paging,
+                huge_pages: config().kernel_huge_pages,
+                verify: true,
+            }
+        }
+    }
+}
+
+/// Wait for a key before going on to `stage`, with the `step` config key
+fn step(stage: &str) {
+    if config().step {
+        println!("Step: {}, press any key to continue", stage);
+        let _ = key(true);
+    }
+}
+
+/// Like `step`, for after boot services have exited, using the serial port
+unsafe fn step_serial(stage: &str) {
+    if config().step {
+        serial::write_str("Step: ");
+        serial::write_str(stage);
+        serial::write_str(", send any byte to continue\n");
+        serial::read_byte();
+    }
+}
+
+/// Keep the splash up for at least `splash_min_ms`, counting the time spent loading
+fn wait_for_splash() {
+    let shown = match unsafe { SPLASH_SHOWN_MS } {
+        Some(shown) => shown,
+        None => return,
+    };
+    let elapsed = time::elapsed_ms().saturating_sub(shown);
+    if elapsed < config().splash_min_ms {
+        let uefi = std::system_table();
+        let _ = (uefi.BootServices.Stall)(((config().splash_min_ms - elapsed) * 1000) as usize);
+    }
+}
+
+/// Get the key held down while starting, F8 for safe mode, F9 to dump the memory map, or
+/// `c` to chainload
+fn startup_key() -> Option<Key> {
+    key(false).ok()
+}
+
+fn inner(flags: BootFlags) -> BootResult<()> {
     //TODO: detect page size?
     let page_size = 4096;
 
+    if flags.safe_mode {
+        println!("");
+        println!("********** SAFE MODE **********");
+        println!("");
+    }
+
+    // paging_enter would fault on a CPU without these, long after the screen is gone
+    if let Some(feature) = cpu::missing_feature() {
+        error!("CPU does not support required feature {}", feature);
+        return Err(BootError::UnsupportedCpu(feature));
+    }
+
+    // Before anything is allocated for the kernel, so as much memory as possible is tested
+    if config().memtest {
+        memtest::run();
+    }
+
+    if let Some(phys_offset) = config().phys_offset {
+        if !valid_phys_offset(phys_offset) {
+            error!("Physical offset {:X} must be canonical, PML4 aligned, and in the higher half", phys_offset);
+            return Err(Error::InvalidParameter.into());
+        }
+        unsafe { PHYS_OFFSET = phys_offset; }
+    }
+
     {
         let mut env = String::new();
         if let Ok(output) = Output::one() {
@@ -222,354 +1067,408 @@ fn inner() -> Result<()> {
             env.push_str(&format!("FRAMEBUFFER_ADDR={:016x}\n", mode.FrameBufferBase));
             env.push_str(&format!("FRAMEBUFFER_WIDTH={:016x}\n", mode.Info.HorizontalResolution));
             env.push_str(&format!("FRAMEBUFFER_HEIGHT={:016x}\n", mode.Info.VerticalResolution));
+
+            if mode.FrameBufferBase != 0 && mode.FrameBufferSize != 0 {
+                unsafe {
+                    FRAMEBUFFER = Some((mode.FrameBufferBase as u64, mode.FrameBufferSize as u64));
+                    FRAMEBUFFER_WC = config().framebuffer_wc;
+                }
+            }
+        }
+
+        let cmdline = unsafe { CMDLINE.as_ref() }.unwrap_or(&config().cmdline);
+        if !cmdline.is_empty() {
+            env.push_str(&format!("CMDLINE={}\n", cmdline));
+        }
+
+        if config().verbose {
+            verbose::dump()?;
         }
 
-        println!("Loading Kernel...");
-        let kernel = if let Ok((_i, mut kernel_file)) = find(KERNEL) {
+        info!("Loading Kernel...");
+        let kernel = if config().kernel_is_url() {
+            let data = fetch_kernel(&config().kernel)?;
+            let kernel = allocate_kernel(data.len() as u64, page_size, flags.load_addr)?;
+            copy_kernel(kernel, &data)?;
+            kernel
+        } else if let Some(mut kernel_file) = find_esp_kernel()? {
+            if let Some(guid) = LoadedImage::current().and_then(|image| image.partition_guid()) {
+                unsafe { BOOT_DISK_GUID = guid; }
+            }
+
             let info = kernel_file.info()?;
             let len = info.FileSize;
 
-            let kernel = unsafe {
-                let ptr = allocate_zero_pages((len as usize + page_size - 1) / page_size)?;
-                slice::from_raw_parts_mut(
-                    ptr as *mut u8,
-                    len as usize
-                )
-            };
+            let kernel = allocate_kernel(len, page_size, flags.load_addr)?;
 
-            let mut i = 0;
-            for mut chunk in kernel.chunks_mut(4 * MB) {
-                print!("\r{}% - {} MB", i as u64 * 100 / len, i / MB);
+            let mut bounce = Vec::new();
+            let mut i: u64 = 0;
+            for chunk in kernel.chunks_mut(4 * MB) {
+                progress(i, len);
 
-                let count = kernel_file.read(&mut chunk)?;
+                let count = read_kernel_chunk(chunk, &mut bounce, |buf| kernel_file.read(buf))?;
                 if count == 0 {
                     break;
                 }
-                //TODO: return error instead of assert
-                assert_eq!(count, chunk.len());
+                if count != chunk.len() {
+                    error!("Short read of kernel at {:X}: {} of {} bytes", i, count, chunk.len());
+                    return Err(Error::DeviceError.into());
+                }
 
-                i += count;
+                i += count as u64;
             }
-            println!("\r{}% - {} MB", i as u64 * 100 / len, i / MB);
+            progress(i, len);
+            progress_done();
 
             kernel
         } else {
-            let mut fs = redoxfs()?;
-
-            let root = fs.header.1.root;
-            let node = fs.find_node("kernel", root).map_err(|_| Error::DeviceError)?;
+            let (mut fs, file) = match find_partition_kernel() {
+                Ok(found) => found,
+                // Every automatic attempt failed, let the user find the kernel
+                Err(err @ BootError::NoBootPartition) | Err(err @ BootError::KernelNotFound) => {
+                    error!("{}", err);
+                    rescue::shell()?
+                },
+                Err(err) => return Err(err),
+            };
 
-            let len = fs.node_len(node.0).map_err(|_| Error::DeviceError)?;
+            let len = fs.file_size(file)?;
 
-            let kernel = unsafe {
-                let ptr = allocate_zero_pages((len as usize + page_size - 1) / page_size)?;
-                println!("{:X}", ptr);
+            let kernel = allocate_kernel(len, page_size, flags.load_addr)?;
+            debug!("Kernel buffer {:X}", kernel.as_ptr() as usize);
 
-                slice::from_raw_parts_mut(
-                    ptr as *mut u8,
-                    len as usize
-                )
-            };
+            let read_start = time::elapsed_ms();
+            disk::reset_read_stats();
 
-            let mut i = 0;
-            for mut chunk in kernel.chunks_mut(4 * MB) {
-                print!("\r{}% - {} MB", i as u64 * 100 / len, i / MB);
+            // The file offset is a u64 so kernels past 4 GiB are read correctly
+            let mut bounce = Vec::new();
+            let mut i: u64 = 0;
+            for chunk in kernel.chunks_mut(4 * MB) {
+                progress(i, len);
 
-                let count = fs.read_node(node.0, i as u64, &mut chunk, 0, 0).map_err(|_| Error::DeviceError)?;
+                let count = read_kernel_chunk(chunk, &mut bounce, |buf| fs.read(file, i, buf))?;
                 if count == 0 {
                     break;
                 }
-                //TODO: return error instead of assert
-                assert_eq!(count, chunk.len());
+                if count != chunk.len() {
+                    error!("Short read of kernel at {:X}: {} of {} bytes", i, count, chunk.len());
+                    return Err(Error::DeviceError.into());
+                }
 
-                i += count;
+                i += count as u64;
+            }
+            progress(i, len);
+            progress_done();
+
+            // Compare with and without read_prefetch to see if it helps on this media
+            info!(
+                "Read kernel in {} ms{}",
+                time::elapsed_ms() - read_start,
+                if config().read_prefetch { " with prefetch" } else { "" }
+            );
+            unsafe {
+                debug!("Boot disk read {} bytes in {} ops with {} retries", READ_STATS.bytes, READ_STATS.ops, READ_STATS.retries);
             }
-            println!("\r{}% - {} MB", i as u64 * 100 / len, i / MB);
 
-            env.push_str(&format!("REDOXFS_BLOCK={:016x}\n", fs.block));
-            env.push_str("REDOXFS_UUID=");
-            for i in 0..fs.header.1.uuid.len() {
-                if i == 4 || i == 6 || i == 8 || i == 10 {
-                    env.push('-');
+            env.push_str(&fs.env());
+
+            kernel
+        };
+
+        let expected_crc = if flags.verify { integrity::expected_kernel_crc32() } else { None };
+        if let Some(expected) = expected_crc {
+            let crc = integrity::crc32(kernel);
+            if crc != expected {
+                error!("Kernel CRC32 is {:08X}, expected {:08X}", crc, expected);
+                if config().crc32_strict {
+                    return Err(BootError::ChecksumMismatch);
                 }
+            } else {
+                info!("Kernel CRC32 {:08X} verified", crc);
+            }
+        }
+
+        // Even in safe mode, a compressed kernel can not be entered as it is
+        let kernel = match compress::maybe_decompress(kernel)? {
+            Some(data) => reallocate_kernel(kernel, &data, data.len() as u64, page_size, flags.load_addr)?,
+            None => kernel,
+        };
 
-                env.push_str(&format!("{:>02x}", fs.header.1.uuid[i]));
+        // A file that is still compressed is never booted as a flat binary
+        let flat_configured = config().flat_entry.is_some() || config().flat_load.is_some();
+        let flat = match config().kernel_format {
+            KernelFormat::Auto => !elf::is_elf(kernel) && flat_configured && !compress::has_magic(kernel),
+            KernelFormat::Elf => false,
+            KernelFormat::Flat => true,
+        };
+        if !flat && !elf::is_elf(kernel) {
+            if compress::has_magic(kernel) {
+                error!("Kernel is compressed with a format that is not supported, compress it with gzip");
+            } else {
+                error!("Kernel does not start with the ELF magic, set kernel_format = flat to boot a flat binary");
             }
+            return Err(BootError::InvalidElf);
+        }
+
+        // Without the magic, the format was detected, so the kernel may not be at flat_load yet
+        let kernel = match config().flat_load {
+            Some(flat_load) if flat && kernel.as_ptr() as u64 != flat_load => {
+                let data = kernel.to_vec();
+                reallocate_kernel(kernel, &data, data.len() as u64, page_size, Some(flat_load))?
+            },
+            _ => kernel,
+        };
 
+        // The .bss of the kernel is past the end of the file, so make room for it and zero it.
+        // A flat kernel has no headers to tell, it has to clear its own.
+        let memory_size = if flat { None } else { kernel_memory_size(kernel) };
+        let kernel = match memory_size {
+            Some(size) if size > kernel.len() as u64 => {
+                let data = kernel.to_vec();
+                let grown = reallocate_kernel(kernel, &data, size, page_size, flags.load_addr)?;
+                unsafe { ptr::write_bytes(grown.as_mut_ptr().add(data.len()), 0, grown.len() - data.len()); }
+                debug!("Zeroed {:X} bytes of kernel .bss", grown.len() - data.len());
+                grown
+            },
+            _ => kernel,
+        };
+
+        let kernel = if !flags.huge_pages {
+            kernel
+        } else if flags.fine_grained_paging {
+            warn!("Ignoring kernel_huge_pages, fine_grained_paging needs 4 KiB pages");
+            kernel
+        } else if flags.load_addr.is_some() || (flat && config().flat_load.is_some()) {
+            warn!("Ignoring kernel_huge_pages, the kernel has a fixed load address");
+            kernel
+        } else {
+            let (kernel, kernel_page_size) = relocate_huge(kernel, page_size)?;
+            unsafe { KERNEL_PAGE_SIZE = kernel_page_size; }
             kernel
         };
 
+        // The entry point is read from the ELF header, or configured for a flat kernel
+        let entry = if flat {
+            let flat_entry = config().flat_entry.unwrap_or(0);
+            info!("Booting a flat kernel, entry offset {:X}", flat_entry);
+            KERNEL_VIRT.saturating_add(flat_entry)
+        } else {
+            elf::entry(kernel).map_err(|_| {
+                error!("Kernel is only {} bytes", kernel.len());
+                BootError::InvalidElf
+            })?
+        };
+
         unsafe {
             KERNEL_PHYS = kernel.as_ptr() as u64;
             KERNEL_SIZE = kernel.len() as u64;
-            KERNEL_ENTRY = *(kernel.as_ptr().offset(0x18) as *const u64);
-            println!("Kernel {:X}:{:X} entry {:X}", KERNEL_PHYS, KERNEL_SIZE, KERNEL_ENTRY);
+            KERNEL_ENTRY = entry;
+            debug!("Kernel {:X}:{:X} entry {:X}", KERNEL_PHYS, KERNEL_SIZE, KERNEL_ENTRY);
+        }
+
+        if kernel.len() as u64 > KERNEL_MAP_SIZE {
+            error!("Kernel too large: {} MB does not fit in the {} MB kernel mapping", kernel.len() / MB, KERNEL_MAP_SIZE as usize / MB);
+            return Err(BootError::OutOfMemory);
+        }
+        check_identity_mapped("Kernel", unsafe { KERNEL_PHYS }, unsafe { KERNEL_SIZE })?;
+
+        step("kernel loaded, allocating the stack and env");
+
+        if !flat {
+            check_kernel_abi(kernel)?;
+        }
+
+        if flags.fine_grained_paging {
+            if flat {
+                warn!("A flat kernel has no segments, mapping all of it writable and executable");
+            } else {
+                let segments = kernel_segments(kernel)?;
+                unsafe { KERNEL_SEGMENTS = segments; }
+            }
+        }
+
+        // A corrupt kernel could have a wild entry point, which would triple fault
+        let entry_offset = match entry.checked_sub(KERNEL_VIRT) {
+            Some(offset) if offset < kernel.len() as u64 => offset,
+            _ => {
+                error!("Kernel entry {:X} is outside of the kernel at {:X}:{:X}", entry, KERNEL_VIRT, kernel.len());
+                return Err(BootError::InvalidElf);
+            }
+        };
+        let segments = unsafe { &KERNEL_SEGMENTS };
+        if !segments.is_empty() && !segments.iter().any(|segment| {
+            entry_offset >= segment.start && entry_offset < segment.end && segment.flags & PF_X != 0
+        }) {
+            error!("Kernel entry {:X} is not in an executable segment", entry);
+            return Err(BootError::InvalidElf);
+        }
+
+        let stack_pages = (config().stack_size as usize + page_size - 1) / page_size;
+        if stack_pages < MIN_STACK_SIZE / page_size || stack_pages > MAX_STACK_SIZE / page_size {
+            error!("Stack size {:X} must be between {:X} and {:X}", config().stack_size, MIN_STACK_SIZE, MAX_STACK_SIZE);
+            return Err(Error::InvalidParameter.into());
         }
 
-        println!("Allocating stack {:X}", STACK_SIZE);
         unsafe {
-            STACK_PHYS = allocate_zero_pages(STACK_SIZE as usize / page_size)? as u64;
-            println!("Stack {:X}:{:X}", STACK_PHYS, STACK_SIZE);
+            STACK_SIZE = (stack_pages * page_size) as u64;
+            debug!("Allocating stack {:X}", STACK_SIZE);
+            STACK_PHYS = allocate_zero_pages(stack_pages)? as u64;
+            debug!("Stack {:X}:{:X}", STACK_PHYS, STACK_SIZE);
         }
+        check_identity_mapped("Stack", unsafe { STACK_PHYS }, unsafe { STACK_SIZE })?;
+
+        unsafe { trampoline::install()?; }
 
-        println!("Allocating env {:X}", env.len());
+        // Configured variables go after the ones from the bootloader
+        for var in config().env.iter() {
+            env.push_str(var);
+            env.push('\n');
+        }
+
+        debug!("Allocating env {:X}", env.len());
         unsafe {
-            ENV_PHYS = allocate_zero_pages((env.len() + page_size - 1) / page_size)? as u64;
+            // The env has its own pages, separate from the stack. Allocate at least one
+            // page, since the env is empty when booting from the ESP without graphics.
+            ENV_PHYS = allocate_zero_pages(cmp::max((env.len() + page_size - 1) / page_size, 1))? as u64;
             ENV_SIZE = env.len() as u64;
             ptr::copy(env.as_ptr(), ENV_PHYS as *mut u8, env.len());
-            println!("Env {:X}:{:X}", ENV_PHYS, ENV_SIZE);
+            debug!("Env {:X}:{:X}", ENV_PHYS, ENV_SIZE);
+        }
+        check_identity_mapped("Env", unsafe { ENV_PHYS }, unsafe { ENV_SIZE })?;
+
+        let version = format!("redox-bootloader {} ({})", env!("CARGO_PKG_VERSION"), env!("GIT_HASH"));
+        unsafe {
+            VERSION_PHYS = allocate_zero_pages((version.len() + page_size - 1) / page_size)? as u64;
+            VERSION_SIZE = version.len() as u64;
+            ptr::copy(version.as_ptr(), VERSION_PHYS as *mut u8, version.len());
+            debug!("Version {:X}:{:X} {}", VERSION_PHYS, VERSION_SIZE, version);
+        }
+        check_identity_mapped("Version", unsafe { VERSION_PHYS }, unsafe { VERSION_SIZE })?;
+
+        let (seed, quality) = rng::seed();
+        unsafe {
+            RNG_SEED_PHYS = allocate_zero_pages(1)? as u64;
+            RNG_SEED_QUALITY = quality;
+            ptr::copy(seed.as_ptr(), RNG_SEED_PHYS as *mut u8, seed.len());
+            drop(seed);
+            debug!("RNG seed {:X} quality {}", RNG_SEED_PHYS, RNG_SEED_QUALITY);
+        }
+        check_identity_mapped("RNG seed", unsafe { RNG_SEED_PHYS }, rng::SEED_SIZE as u64)?;
+
+        // Boot services are needed to find the image, so record it before exiting them
+        if let Some(image) = LoadedImage::current() {
+            unsafe {
+                BOOTLOADER_BASE = image.0.ImageBase as u64;
+                BOOTLOADER_SIZE = image.0.ImageSize;
+            }
+            debug!("Bootloader image {:X}:{:X}", image.0.ImageBase, image.0.ImageSize);
+        }
+
+        let (modules_phys, modules_count) = modules::load(page_size)?;
+        unsafe {
+            MODULES_PHYS = modules_phys;
+            MODULES_COUNT = modules_count;
         }
 
-        println!("Parsing and writing ACPI RSDP structures.");
-        find_acpi_table_pointers();
+        memory_map::relocate_if_reserved(page_size)?;
+        check_identity_mapped("Memory map", memory_map::area().0, memory_map::area().1)?;
+
+        debug!("Parsing and writing ACPI RSDP structures.");
+        find_acpi_table_pointers(page_size)?;
 
         println!("Done!");
     }
 
-    println!("Creating page tables");
+    step("creating page tables");
+
+    info!("Creating page tables");
     let page_phys = unsafe {
-        paging_create(KERNEL_PHYS)?
+        paging_create(KERNEL_PHYS, PHYS_OFFSET, &KERNEL_SEGMENTS, KERNEL_PAGE_SIZE, FRAMEBUFFER, FRAMEBUFFER_WC)?
     };
 
-    println!("Entering kernel");
-    unsafe {
-        let key = memory_map();
-        exit_boot_services(key);
-    }
+    // Calibrating may need boot services, so it has to happen before they exit
+    unsafe { TSC_FREQ = time::frequency(); }
 
-    unsafe {
-        llvm_asm!("cli" : : : "memory" : "intel", "volatile");
-        paging_enter(page_phys);
-    }
+    println!("Bootloader took {} ms", time::elapsed_ms());
 
-    unsafe {
-        llvm_asm!("mov rsp, $0" : : "r"(STACK_PHYS + PHYS_OFFSET + STACK_SIZE) : "memory" : "intel", "volatile");
-        enter();
+    if cfg!(debug_assertions) || config().debug {
+        dump_kernel_args(unsafe { &kernel_args() });
     }
-}
-
-fn draw_text(display: &mut ScaledDisplay, mut x: i32, y: i32, text: &str, color: Color) {
-    for c in text.chars() {
-        display.char(x, y, c, color);
-        x += 8;
+    // Only on request, so unattended debug builds still boot
+    if config().debug {
+        println!("Press any key to continue");
+        let _ = key(true);
     }
-}
 
-fn draw_background(display: &mut ScaledDisplay, splash: &Image) {
-    let bg = Color::rgb(0x4a, 0xa3, 0xfd);
+    wait_for_splash();
 
-    display.set(bg);
+    let debug = config().debug;
 
-    {
-        let x = (display.width() as i32 - splash.width() as i32)/2;
-        let y = 16;
-        splash.draw(display, x, y);
-    }
+    step("exiting boot services");
 
-    {
-        let prompt = format!(
-            "Redox Bootloader {} {}",
-            env!("CARGO_PKG_VERSION"),
-            env!("TARGET").split('-').next().unwrap_or("")
-        );
-        let x = (display.width() as i32 - prompt.len() as i32 * 8)/2;
-        let y = display.height() as i32 - 32;
-        draw_text(display, x, y, &prompt, Color::rgb(0xff, 0xff, 0xff));
-    }
-}
-
-fn select_mode(output: &mut Output, splash: &Image) -> Result<()> {
-    // Read all available modes
-    let mut modes = Vec::new();
-    for i in 0..output.0.Mode.MaxMode {
-        let mut mode_ptr = ::core::ptr::null_mut();
-        let mut mode_size = 0;
-        (output.0.QueryMode)(output.0, i, &mut mode_size, &mut mode_ptr)?;
-
-        let mode = unsafe { &mut *mode_ptr };
-        let w = mode.HorizontalResolution;
-        let h = mode.VerticalResolution;
-
-        let mut aspect_w = w;
-        let mut aspect_h = h;
-        for i in 2..cmp::min(aspect_w / 2, aspect_h / 2) {
-            while aspect_w % i == 0 && aspect_h % i == 0 {
-                aspect_w /= i;
-                aspect_h /= i;
-            }
-        }
-
-        //TODO: support resolutions that are not perfect multiples of 4
-        if w % 4 != 0 {
-            continue;
-        }
+    println!("Entering kernel");
+    // The kernel takes over from here, so the firmware must not reset it
+    watchdog::disarm();
 
-        modes.push((i, w, h, format!("{:>4}x{:<4} {:>3}:{:<3}", w, h, aspect_w, aspect_h)));
+    unsafe {
+        secret::scrub_all();
+        exit_boot_services();
     }
 
-    // Sort modes by pixel area, reversed
-    modes.sort_by(|a, b| (b.1 * b.2).cmp(&(a.1 * a.2)));
-
-    // Find current mode index
-    let mut selected = output.0.Mode.Mode;
-
-    // If there are no modes from querymode, don't change mode
-    if modes.is_empty() {
-        return Ok(());
+    // Boot services are gone, so only the serial port is left for output. A marker is
+    // written at each step from here to the kernel, so a hang can be narrowed down: `E`
+    // after exiting boot services, then `P`, `S`, and `J` from the trampoline.
+    unsafe {
+        serial::init();
+        serial::write_byte(b'E');
     }
 
-    let white = Color::rgb(0xff, 0xff, 0xff);
-    let black = Color::rgb(0x00, 0x00, 0x00);
-    let rows = 12;
-    loop {
-        {
-            // Create a scaled display
-            let mut display = Display::new(output);
-            let mut display = ScaledDisplay::new(&mut display);
-
-            draw_background(&mut display, splash);
-
-            let off_x = (display.width() as i32 - 60 * 8)/2;
-            let mut off_y = 16 + splash.height() as i32 + 16;
-            draw_text(
-                &mut display,
-                off_x, off_y,
-                "Arrow keys and enter select mode",
-                white
-            );
-            off_y += 24;
-
-            let mut row = 0;
-            let mut col = 0;
-            for (i, w, h, text) in modes.iter() {
-                if row >= rows as i32 {
-                    col += 1;
-                    row = 0;
-                }
-
-                let x = off_x + col * 20 * 8;
-                let y = off_y + row * 16;
-
-                let fg = if *i == selected {
-                    display.rect(x - 8, y, text.len() as u32 * 8 + 16, 16, white);
-                    black
-                } else {
-                    white
-                };
+    unsafe { step_serial("exited boot services, enabling paging"); }
 
-                draw_text(&mut display, x, y, text, fg);
+    if unsafe { DUMP_MEMORY_MAP } {
+        unsafe { memory_map::dump_serial(); }
+    }
 
-                row += 1;
-            }
+    if debug {
+        unsafe { self_test(); }
+    }
 
-            display.sync();
+    unsafe {
+        llvm_asm!("cli" : : : "memory" : "intel", "volatile");
+        paging_enter();
+        if FRAMEBUFFER_WC && FRAMEBUFFER.is_some() {
+            paging::pat_write_combining();
         }
+    }
 
-        match key(true)? {
-            Key::Left => {
-                if let Some(mut mode_i) = modes.iter().position(|x| x.0 == selected) {
-                    if mode_i < rows {
-                        while mode_i < modes.len() {
-                            mode_i += rows;
-                        }
-                    }
-                    mode_i -= rows;
-                    if let Some(new) = modes.get(mode_i) {
-                        selected = new.0;
-                    }
-                }
-            },
-            Key::Right => {
-                if let Some(mut mode_i) = modes.iter().position(|x| x.0 == selected) {
-                    mode_i += rows;
-                    if mode_i >= modes.len() {
-                        mode_i = mode_i % rows;
-                    }
-                    if let Some(new) = modes.get(mode_i) {
-                        selected = new.0;
-                    }
-                }
-            },
-            Key::Up => {
-                if let Some(mut mode_i) = modes.iter().position(|x| x.0 == selected) {
-                    if mode_i % rows == 0 {
-                        mode_i += rows;
-                        if mode_i > modes.len() {
-                            mode_i = modes.len();
-                        }
-                    }
-                    mode_i -= 1;
-                    if let Some(new) = modes.get(mode_i) {
-                        selected = new.0;
-                    }
-                }
-            },
-            Key::Down => {
-                if let Some(mut mode_i) = modes.iter().position(|x| x.0 == selected) {
-                    mode_i += 1;
-                    if mode_i % rows == 0 {
-                        mode_i -= rows;
-                    }
-                    if mode_i >= modes.len() {
-                        mode_i = mode_i - mode_i % rows;
-                    }
-                    if let Some(new) = modes.get(mode_i) {
-                        selected = new.0;
-                    }
-                }
-            },
-            Key::Enter => {
-                (output.0.SetMode)(output.0, selected)?;
-                return Ok(());
-            },
-            _ => (),
-        }
+    unsafe {
+        step_serial("entering kernel");
+        enter(page_phys);
     }
 }
 
-fn pretty_pipe<T, F: FnMut() -> Result<T>>(output: &mut Output, splash: &Image, f: F) -> Result<T> {
-    let mut display = Display::new(output);
-
-    let mut display = ScaledDisplay::new(&mut display);
+pub fn main() -> BootResult<()> {
+    let startup_key = startup_key();
+    let safe_mode = config().safe_mode || startup_key == Some(Key::F8);
+    unsafe { DUMP_MEMORY_MAP = config().verbose || startup_key == Some(Key::F9); }
 
-    draw_background(&mut display, splash);
-
-    display.sync();
+    // Without the graphical menu, holding c is the only way to chainload
+    if startup_key == Some(Key::Character('c')) {
+        if let Some(path) = &config().chainload {
+            chainload(path)?;
+        }
+    }
 
+    #[cfg(not(feature = "minimal"))]
     {
-        let cols = 80;
-        let off_x = (display.width() as i32 - cols as i32 * 8)/2;
-        let off_y = 16 + splash.height() as i32 + 16;
-        let rows = (display.height() as i32 - 64 - off_y - 1) as usize/16;
-        display.rect(off_x, off_y, cols as u32 * 8, rows as u32 * 16, Color::rgb(0, 0, 0));
-        display.sync();
-
-        let mut text = TextDisplay::new(display);
-        text.off_x = off_x;
-        text.off_y = off_y;
-        text.cols = cols;
-        text.rows = rows;
-        text.pipe(f)
-    }
-}
-
-pub fn main() -> Result<()> {
-    if let Ok(mut output) = Output::one() {
-        let mut splash = Image::new(0, 0);
-        {
-            println!("Loading Splash...");
-            if let Ok(image) = image::bmp::parse(&SPLASHBMP) {
-                splash = image;
-            }
-            println!(" Done");
+        let mut backend = Backend::one();
+        if backend.is_graphical() {
+            return ui::main(&mut backend, safe_mode);
         }
-
-        select_mode(&mut output, &splash)?;
-
-        pretty_pipe(&mut output, &splash, inner)?;
-    } else {
-        inner()?;
     }
 
+    let flags = BootFlags::new(safe_mode);
+    text_pipe(|| Ok(inner(flags)))??;
+
     Ok(())
 }
@@ -0,0 +1,79 @@
+use core::{mem, ptr};
+use std::vec::Vec;
+use uefi::status::{Error, Result};
+
+use crate::config::{config, esp_path};
+use crate::fs::read_file;
+
+use super::{allocate_zero_pages, check_identity_mapped};
+
+/// Most modules that may be passed to the kernel
+const MAX_MODULES: usize = 64;
+/// Most bytes of module data that may be passed to the kernel
+const MAX_MODULES_SIZE: u64 = 512 * 1024 * 1024;
+
+/// Describes one module in the table at `KernelArgs.modules_base`
+#[derive(Clone, Copy, Debug)]
+#[repr(packed)]
+pub struct ModuleDescriptor {
+    /// Physical address of the module data
+    pub base: u64,
+    /// Size of the module data in bytes
+    pub size: u64,
+    /// Offset of the NUL terminated name, from the start of the table
+    pub name_offset: u64,
+}
+
+/// Load every configured module, returning the table base and the number of modules
+///
+/// The table holds one `ModuleDescriptor` per module, followed by their names.
+pub fn load(page_size: usize) -> Result<(u64, u64)> {
+    let names = &config().modules;
+    if names.is_empty() {
+        return Ok((0, 0));
+    }
+    if names.len() > MAX_MODULES {
+        error!("{} modules configured, at most {} are supported", names.len(), MAX_MODULES);
+        return Err(Error::OutOfResources);
+    }
+
+    let mut descriptors = Vec::with_capacity(names.len());
+    let mut strings = Vec::new();
+    let mut total = 0;
+    for name in names.iter() {
+        let data = read_file(&esp_path(name)).map_err(|err| {
+            error!("Module {} not found", name);
+            err
+        })?;
+
+        total += data.len() as u64;
+        if total > MAX_MODULES_SIZE {
+            error!("Modules are over the maximum of {} MB", MAX_MODULES_SIZE / 1024 / 1024);
+            return Err(Error::OutOfResources);
+        }
+
+        let base = unsafe { allocate_zero_pages((data.len() + page_size - 1) / page_size)? };
+        unsafe { ptr::copy(data.as_ptr(), base as *mut u8, data.len()); }
+        check_identity_mapped("Module", base as u64, data.len() as u64)?;
+        debug!("Module {:X}:{:X} {}", base, data.len(), name);
+
+        descriptors.push(ModuleDescriptor {
+            base: base as u64,
+            size: data.len() as u64,
+            name_offset: (names.len() * mem::size_of::<ModuleDescriptor>() + strings.len()) as u64,
+        });
+        strings.extend_from_slice(name.as_bytes());
+        strings.push(0);
+    }
+
+    let table_size = descriptors.len() * mem::size_of::<ModuleDescriptor>();
+    let size = table_size + strings.len();
+    let table = unsafe { allocate_zero_pages((size + page_size - 1) / page_size)? };
+    unsafe {
+        ptr::copy(descriptors.as_ptr(), table as *mut ModuleDescriptor, descriptors.len());
+        ptr::copy(strings.as_ptr(), (table + table_size) as *mut u8, strings.len());
+    }
+    check_identity_mapped("Modules", table as u64, size as u64)?;
+
+    Ok((table as u64, descriptors.len() as u64))
+}
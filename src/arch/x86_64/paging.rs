@@ -5,6 +5,83 @@ use x86::{
 };
 use uefi::status::Result;
 
+use crate::elf::{PF_W, PF_X};
+
+/// Size of the identity map of low physical memory, also mapped at the physical offset
+pub const IDENTITY_MAP_SIZE: u64 = 8 * 0x4000_0000;
+
+/// Size of the kernel mapping
+pub const KERNEL_MAP_SIZE: u64 = 0x4000_0000;
+
+/// Virtual address of the kernel mapping
+pub const KERNEL_VIRT: u64 = 0xFFFF_FF00_0000_0000;
+
+/// Virtual address of the framebuffer mapping, in the PML4 entry below the kernel mapping
+pub const FRAMEBUFFER_VIRT: u64 = 0xFFFF_FE80_0000_0000;
+
+/// Size of the region mapped by one PML4 entry
+pub const PML4_ENTRY_SIZE: u64 = 0x80_0000_0000;
+
+/// Size of a page mapped by one PDP entry
+pub const PAGE_SIZE_1G: u64 = 0x4000_0000;
+
+/// Size of a page mapped by one PD entry
+pub const PAGE_SIZE_2M: u64 = 0x20_0000;
+
+/// PDP or PD entry bit that maps a page instead of pointing to a table
+const PTE_PS: u64 = 1 << 7;
+
+/// Page table entry bit that selects the upper half of the PAT
+const PTE_PAT: u64 = 1 << 7;
+
+/// PAT memory type for write-combining
+const PAT_WC: u64 = 0x01;
+
+/// Check that the physical memory offset is canonical, PML4 aligned, and in the higher half
+/// without overlapping the framebuffer, kernel, or recursive mappings
+pub fn valid_phys_offset(offset: u64) -> bool {
+    let canonical = offset >> 47 == 0x1_FFFF;
+    let index = (offset >> 39) & 0x1FF;
+    canonical && offset % PML4_ENTRY_SIZE == 0 && index != 509 && index != 510 && index != 511
+}
+
+/// A region of the kernel mapping with ELF segment permissions
+#[derive(Clone, Copy, Debug)]
+pub struct Segment {
+    /// Offset from the start of the kernel mapping
+    pub start: u64,
+    pub end: u64,
+    /// ELF `p_flags`
+    pub flags: u32,
+}
+
+/// Get the page table entry flags for a page of the kernel mapping
+fn kernel_page_flags(segments: &[Segment], offset: u64) -> u64 {
+    let mut p_flags = None;
+    for segment in segments {
+        // Segments sharing a page get the union of their permissions
+        if offset < segment.end && offset + 0x1000 > segment.start {
+            p_flags = Some(p_flags.unwrap_or(0) | segment.flags);
+        }
+    }
+
+    match p_flags {
+        Some(p_flags) => {
+            let mut flags = 1;
+            if p_flags & PF_W != 0 {
+                flags |= 1 << 1;
+            }
+            if p_flags & PF_X == 0 {
+                // No execute, requires NXE in EFER which paging_enter sets
+                flags |= 1 << 63;
+            }
+            flags
+        },
+        // Pages outside of the segments stay read-write
+        None => 1 << 1 | 1,
+    }
+}
+
 unsafe fn paging_allocate() -> Result<&'static mut [u64]> {
     let ptr = super::allocate_zero_pages(1)?;
 
@@ -14,7 +91,27 @@ unsafe fn paging_allocate() -> Result<&'static mut [u64]> {
     ))
 }
 
-pub unsafe fn paging_create(kernel_phys: u64) -> Result<u64> {
+/// Get the table that entry `i` of `table` points to, creating it if the entry is empty
+unsafe fn paging_next(table: &mut [u64], i: usize) -> Result<&'static mut [u64]> {
+    if table[i] == 0 {
+        let next = paging_allocate()?;
+        table[i] = next.as_ptr() as u64 | 1 << 1 | 1;
+        Ok(next)
+    } else {
+        Ok(slice::from_raw_parts_mut((table[i] & 0x000F_FFFF_FFFF_F000) as *mut u64, 512))
+    }
+}
+
+/// Create page tables, identity mapping low memory at zero and at `phys_offset`, mapping
+/// the kernel with per-segment permissions if `segments` is not empty, and mapping the
+/// `framebuffer` base and size at `FRAMEBUFFER_VIRT`. With `write_combining`, framebuffer
+/// pages select PAT entry 4, see `pat_write_combining`.
+///
+/// The kernel is mapped with pages of `kernel_page_size`, `PAGE_SIZE_1G` and `PAGE_SIZE_2M`
+/// need `kernel_phys` aligned to them and map everything read-write and executable.
+pub unsafe fn paging_create(kernel_phys: u64, phys_offset: u64, segments: &[Segment], kernel_page_size: u64, framebuffer: Option<(u64, u64)>, write_combining: bool) -> Result<u64> {
+    let framebuffer_flags = if write_combining { PTE_PAT } else { 0 };
+
     // Create PML4
     let pml4 = paging_allocate()?;
 
@@ -25,12 +122,12 @@ pub unsafe fn paging_create(kernel_phys: u64) -> Result<u64> {
         // Create PDP for identity mapping
         let pdp = paging_allocate()?;
 
-        // Link first user and first kernel PML4 entry to PDP
+        // Link first user PML4 entry and the physical offset's entry to PDP
         pml4[0] = pdp.as_ptr() as u64 | 1 << 1 | 1;
-        pml4[256] = pdp.as_ptr() as u64 | 1 << 1 | 1;
+        pml4[((phys_offset >> 39) & 0x1FF) as usize] = pdp.as_ptr() as u64 | 1 << 1 | 1;
 
         // Identity map 8 GiB pages
-        for pdp_i in 0..8 {
+        for pdp_i in 0..(IDENTITY_MAP_SIZE / 0x4000_0000) as usize {
             let pd = paging_allocate()?;
             pdp[pdp_i] = pd.as_ptr() as u64 | 1 << 1 | 1;
             for pd_i in 0..pd.len() {
@@ -42,6 +139,11 @@ pub unsafe fn paging_create(kernel_phys: u64) -> Result<u64> {
                         pd_i as u64 * 0x20_0000 +
                         pt_i as u64 * 0x1000;
                     pt[pt_i] = addr | 1 << 1 | 1;
+                    if let Some((base, size)) = framebuffer {
+                        if addr + 0x1000 > base && addr < base + size {
+                            pt[pt_i] |= framebuffer_flags;
+                        }
+                    }
                 }
             }
         }
@@ -55,28 +157,53 @@ pub unsafe fn paging_create(kernel_phys: u64) -> Result<u64> {
         pml4[510] = pdp.as_ptr() as u64 | 1 << 1 | 1;
 
         // Map 1 GiB at kernel offset
-        for pdp_i in 0..1 {
+        for pdp_i in 0..(KERNEL_MAP_SIZE / 0x4000_0000) as usize {
+            if kernel_page_size == PAGE_SIZE_1G {
+                pdp[pdp_i] = (pdp_i as u64 * 0x4000_0000 + kernel_phys) | PTE_PS | 1 << 1 | 1;
+                continue;
+            }
+
             let pd = paging_allocate()?;
             pdp[pdp_i] = pd.as_ptr() as u64 | 1 << 1 | 1;
             for pd_i in 0..pd.len() {
+                if kernel_page_size == PAGE_SIZE_2M {
+                    let offset = pdp_i as u64 * 0x4000_0000 + pd_i as u64 * 0x20_0000;
+                    pd[pd_i] = (offset + kernel_phys) | PTE_PS | 1 << 1 | 1;
+                    continue;
+                }
+
                 let pt = paging_allocate()?;
                 pd[pd_i] = pt.as_ptr() as u64 | 1 << 1 | 1;
                 for pt_i in 0..pt.len() {
-                    let addr =
+                    let offset =
                         pdp_i as u64 * 0x4000_0000 +
                         pd_i as u64 * 0x20_0000 +
-                        pt_i as u64 * 0x1000 +
-                        kernel_phys;
-                    pt[pt_i] = addr | 1 << 1 | 1;
+                        pt_i as u64 * 0x1000;
+                    pt[pt_i] = (offset + kernel_phys) | kernel_page_flags(segments, offset);
                 }
             }
         }
     }
 
+    if let Some((base, size)) = framebuffer {
+        // The framebuffer may cross any number of page tables, so they are created as needed
+        let pdp = paging_next(pml4, ((FRAMEBUFFER_VIRT >> 39) & 0x1FF) as usize)?;
+        let start = base & !0xFFF;
+        let end = (base + size + 0xFFF) & !0xFFF;
+        for addr in (start..end).step_by(0x1000) {
+            let offset = addr - start;
+            let pd = paging_next(pdp, ((offset >> 30) & 0x1FF) as usize)?;
+            let pt = paging_next(pd, ((offset >> 21) & 0x1FF) as usize)?;
+            // No execute, requires NXE in EFER which paging_enter sets
+            pt[((offset >> 12) & 0x1FF) as usize] = addr | framebuffer_flags | 1 << 63 | 1 << 1 | 1;
+        }
+    }
+
     Ok(pml4.as_ptr() as u64)
 }
 
-pub unsafe fn paging_enter(page_phys: u64) {
+/// Set up the control registers for the new page tables, which the trampoline then loads
+pub unsafe fn paging_enter() {
     // Enable OSXSAVE, FXSAVE/FXRSTOR, Page Global, Page Address Extension, and Page Size Extension
     let mut cr4 = controlregs::cr4();
     cr4 |= Cr4::CR4_ENABLE_OS_XSAVE
@@ -86,16 +213,25 @@ pub unsafe fn paging_enter(page_phys: u64) {
         | Cr4::CR4_ENABLE_PSE;
     controlregs::cr4_write(cr4);
 
-    // Enable Long mode and NX bit
+    // Enable Long mode and NX bit, the NX bit must stay set since kernel pages may use it
     let mut efer = msr::rdmsr(msr::IA32_EFER);
     efer |= 1 << 11 | 1 << 8;
     msr::wrmsr(msr::IA32_EFER, efer);
 
-    // Set new page map
-    controlregs::cr3_write(page_phys);
-
     // Enable paging, write protect kernel, protected mode
     let mut cr0 = controlregs::cr0();
     cr0 |= Cr0::CR0_ENABLE_PAGING | Cr0::CR0_WRITE_PROTECT | Cr0::CR0_PROTECTED_MODE;
     controlregs::cr0_write(cr0);
 }
+
+/// Make PAT entry 4 write-combining. It defaults to write-back like entry 0, and only pages
+/// with `PTE_PAT` select it, so mappings made by the firmware are unchanged.
+pub unsafe fn pat_write_combining() {
+    let mut pat = msr::rdmsr(msr::IA32_PAT);
+    pat = pat & !(0xFF << 32) | PAT_WC << 32;
+
+    // Caches must not hold lines of the old type when the PAT changes
+    llvm_asm!("wbinvd" : : : "memory" : "intel", "volatile");
+    msr::wrmsr(msr::IA32_PAT, pat);
+    llvm_asm!("wbinvd" : : : "memory" : "intel", "volatile");
+}
@@ -1,5 +1,7 @@
 use std::proto::Protocol;
 
+use crate::disk::DiskEfi;
+
 #[repr(packed)]
 #[derive(Clone, Copy, Debug)]
 pub struct PartitionProtoInfoMbr {
@@ -44,6 +46,15 @@ pub const ESP_GUID: [u8; 16] = [0x28, 0x73, 0x2a, 0xc1, 0x1f, 0xf8, 0xd2, 0x11,
 pub const LINUX_FS_GUID: [u8; 16] = [0xaf, 0x3d, 0xc6, 0xf, 0x83, 0x84, 0x72, 0x47, 0x8e, 0x79, 0x3d, 0x69, 0xd8, 0x47, 0x7d, 0xe4]; // 0fc63daf-8483-4772-8e79-3d69d8477de4
 pub const REDOX_FS_GUID: [u8; 16] = [0xfd, 0x98, 0x78, 0x52, 0xe3, 0xff, 0xc2, 0x42, 0xe3, 0x96, 0x10, 0x5b, 0xa6, 0x3f, 0x5a, 0xbf]; // 527898fd-ffe3-42c2-96e3-bf5a3fa65b10
 
+/// GPT attribute for partitions the platform requires, which must be left alone
+pub const GPT_ATTR_REQUIRED: u64 = 1 << 0;
+/// GPT attribute for partitions the firmware should not treat as a block device
+pub const GPT_ATTR_NO_BLOCK_IO: u64 = 1 << 1;
+/// GPT attribute for partitions marked bootable, by legacy BIOS convention
+pub const GPT_ATTR_LEGACY_BOOTABLE: u64 = 1 << 2;
+/// GPT attribute for read-only partitions, defined for basic data partitions
+pub const GPT_ATTR_READ_ONLY: u64 = 1 << 60;
+
 #[repr(u32)]
 pub enum PartitionProtoDataTy {
     Other = 0,
@@ -61,3 +72,53 @@ impl Protocol<PartitionProtoData> for PartitionProto {
         Self(inner)
     }
 }
+
+/// MBR partition types for extended partitions, which hold a chain of EBRs
+pub const MBR_EXTENDED_TYPES: [u8; 3] = [0x05, 0x0F, 0x85];
+
+/// Limit on the number of EBRs to follow, in case the chain loops
+const MAX_EBRS: usize = 128;
+
+/// Follow the EBR chain of an extended partition, returning the first LBA, relative to the
+/// extended partition, of a logical partition with MBR type `ty`
+pub fn find_logical(disk: &mut DiskEfi, ty: u8) -> Option<u64> {
+    let block_size = disk.0.Media.BlockSize as usize;
+    if block_size < 512 {
+        return None;
+    }
+
+    let mut sector = vec![0; block_size];
+    let mut ebr_lba = 0;
+    for _ in 0..MAX_EBRS {
+        if disk.read_blocks(ebr_lba, &mut sector).is_err() {
+            warn!("Failed to read EBR at {:X}", ebr_lba);
+            return None;
+        }
+        if sector[510] != 0x55 || sector[511] != 0xAA {
+            warn!("Invalid EBR signature at {:X}", ebr_lba);
+            return None;
+        }
+
+        let entry = |i: usize| {
+            let offset = 446 + i * 16;
+            let mut start = [0; 4];
+            start.copy_from_slice(&sector[offset + 8..offset + 12]);
+            (sector[offset + 4], u32::from_le_bytes(start) as u64)
+        };
+
+        // The first entry is relative to this EBR, the second to the extended partition
+        let (logical_ty, logical_start) = entry(0);
+        if logical_ty == ty && logical_start != 0 {
+            return Some(ebr_lba + logical_start);
+        }
+
+        let (next_ty, next_start) = entry(1);
+        if next_ty == 0 || next_start == 0 || next_start == ebr_lba {
+            return None;
+        }
+        ebr_lba = next_start;
+    }
+
+    warn!("EBR chain is longer than {} entries", MAX_EBRS);
+    None
+}
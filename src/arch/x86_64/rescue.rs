@@ -0,0 +1,108 @@
+//! A small shell for when no kernel can be found, to boot from a typed path
+
+use std::boxed::Box;
+use std::vec::Vec;
+use uefi::Handle;
+use uefi::status::Result;
+
+use crate::device_path;
+use crate::disk::DiskEfi;
+use crate::edit::edit_line;
+use crate::error::{BootError, BootResult};
+use crate::fs::{self, BootFs, FsKind};
+use crate::proto::{locate_handles, OpenProtocol};
+
+/// A partition that the shell can boot from
+struct Partition {
+    handle: Handle,
+    /// Size in bytes
+    size: u64,
+    kind: Option<FsKind>,
+}
+
+/// Find every partition, with the filesystem at its start if it is supported
+fn find_partitions() -> Result<Vec<Partition>> {
+    let mut handles = locate_handles(&uefi::guid::BLOCK_IO_GUID)?;
+    device_path::sort_handles(&mut handles);
+
+    let mut partitions = Vec::new();
+    for handle in handles {
+        let mut block_io = match OpenProtocol::<DiskEfi>::open(handle) {
+            Ok(block_io) => block_io,
+            Err(_) => continue,
+        };
+        if !block_io.0.Media.LogicalPartition {
+            continue;
+        }
+
+        let size = (block_io.0.Media.LastBlock + 1) * block_io.0.Media.BlockSize as u64;
+        let kind = fs::detect(&mut *block_io, 0).ok();
+        partitions.push(Partition { handle, size, kind });
+    }
+    Ok(partitions)
+}
+
+fn list(partitions: &[Partition]) {
+    if partitions.is_empty() {
+        println!("No partitions found");
+    }
+    for (i, partition) in partitions.iter().enumerate() {
+        let kind = match partition.kind {
+            Some(FsKind::RedoxFs) => "redoxfs",
+            Some(FsKind::Ext) => "ext",
+            None => "unknown",
+        };
+        println!("{}: {} MB {} (handle {:X})", i, partition.size / 1024 / 1024, kind, partition.handle.0);
+    }
+}
+
+/// Open the filesystem on `partition` and find `path` in it
+fn open(partition: &Partition, path: &str) -> Result<(Box<dyn BootFs>, u64)> {
+    let disk = OpenProtocol::<DiskEfi>::open(partition.handle)?.leak();
+    let mut fs = fs::open(disk, None)?;
+    let file = fs.find_path(path)?;
+    Ok((fs, file))
+}
+
+/// Prompt for commands until a kernel is found, returning its filesystem and file
+pub fn shell() -> BootResult<(Box<dyn BootFs>, u64)> {
+    println!("");
+    println!("No kernel was found, starting the rescue shell");
+    println!("Type help for a list of commands");
+
+    let mut partitions = find_partitions()?;
+    loop {
+        let line = match edit_line("rescue> ", "")? {
+            Some(line) => line,
+            None => continue,
+        };
+
+        let mut args = line.split_whitespace();
+        match args.next() {
+            Some("help") => {
+                println!("ls                   list partitions");
+                println!("boot <number> <path> boot the kernel at path on a partition");
+                println!("exit                 give up and reset");
+            },
+            Some("ls") => {
+                partitions = find_partitions()?;
+                list(&partitions);
+            },
+            Some("boot") => {
+                let partition = args.next()
+                    .and_then(|number| number.parse::<usize>().ok())
+                    .and_then(|i| partitions.get(i));
+                match (partition, args.next()) {
+                    (Some(partition), Some(path)) => match open(partition, path) {
+                        Ok(found) => return Ok(found),
+                        Err(err) => println!("Failed to open {}: {:?}", path, err),
+                    },
+                    _ => println!("Usage: boot <number> <path>, see ls for numbers"),
+                }
+            },
+            Some("exit") => return Err(BootError::KernelNotFound),
+            Some(command) => println!("Unknown command {}", command),
+            None => (),
+        }
+    }
+}
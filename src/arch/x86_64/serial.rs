@@ -0,0 +1,46 @@
+//! Minimal COM1 output that works without boot services
+
+use x86::io::{inb, outb};
+
+const COM1: u16 = 0x3F8;
+
+/// Set up COM1 for 115200 baud, 8N1
+pub unsafe fn init() {
+    outb(COM1 + 1, 0x00); // Disable interrupts
+    outb(COM1 + 3, 0x80); // Enable divisor latch
+    outb(COM1 + 0, 0x01); // Divisor low byte, 115200 baud
+    outb(COM1 + 1, 0x00); // Divisor high byte
+    outb(COM1 + 3, 0x03); // 8 bits, no parity, one stop bit
+    outb(COM1 + 2, 0xC7); // Enable and clear FIFOs
+    outb(COM1 + 4, 0x0B); // Enable DTR, RTS, and OUT2
+}
+
+pub unsafe fn write_byte(byte: u8) {
+    // Wait for the transmit holding register to be empty
+    while inb(COM1 + 5) & 0x20 == 0 {}
+    outb(COM1, byte);
+}
+
+/// Wait for a byte to arrive. Without a serial port, this returns right away.
+pub unsafe fn read_byte() -> u8 {
+    // Wait for the data ready bit
+    while inb(COM1 + 5) & 0x01 == 0 {}
+    inb(COM1)
+}
+
+pub unsafe fn write_str(string: &str) {
+    for byte in string.bytes() {
+        if byte == b'\n' {
+            write_byte(b'\r');
+        }
+        write_byte(byte);
+    }
+}
+
+/// Write a number in hexadecimal, without allocating
+pub unsafe fn write_hex(value: u64) {
+    for i in (0..16).rev() {
+        let digit = (value >> (i * 4)) as u8 & 0xF;
+        write_byte(if digit < 10 { b'0' + digit } else { b'A' + digit - 10 });
+    }
+}
@@ -0,0 +1,63 @@
+//! A page of code that switches to the new page tables and jumps to the kernel
+//!
+//! Loading CR3 while running from the bootloader image only works if the image happens to be
+//! identity mapped by the new tables. The trampoline is copied into a page below the identity
+//! map limit instead, so the instructions after the switch are always mapped.
+
+use core::{mem, ptr};
+use uefi::memory::MemoryType;
+use uefi::status::Result;
+
+use super::paging::IDENTITY_MAP_SIZE;
+
+/// Called as `extern "sysv64" fn(page_phys, stack, entry, args) -> !`
+///
+/// A marker byte is written to COM1 after each step, `P` after the page tables are loaded,
+/// `S` after the stack switch, and `J` right before the jump. The FIFO has room for them,
+/// so the transmitter is not polled.
+const CODE: [u8; 30] = [
+    0x0F, 0x22, 0xDF,       // mov cr3, rdi
+    0x49, 0x89, 0xD0,       // mov r8, rdx, as dx is needed for the port
+    0x66, 0xBA, 0xF8, 0x03, // mov dx, 0x3F8
+    0xB0, b'P',             // mov al, 'P'
+    0xEE,                   // out dx, al
+    0x48, 0x89, 0xF4,       // mov rsp, rsi
+    0xB0, b'S',             // mov al, 'S'
+    0xEE,                   // out dx, al
+    0x48, 0x89, 0xCF,       // mov rdi, rcx
+    0x6A, 0x00,             // push 0, a return address for the kernel's stack alignment
+    0xB0, b'J',             // mov al, 'J'
+    0xEE,                   // out dx, al
+    0x41, 0xFF, 0xE0,       // jmp r8
+];
+
+static mut TRAMPOLINE_PHYS: u64 = 0;
+
+/// Copy the trampoline to a page inside of the identity map, this needs boot services
+pub unsafe fn install() -> Result<()> {
+    let uefi = std::system_table();
+
+    // Loader code, so firmware that protects its memory leaves the page executable
+    let mut ptr = (IDENTITY_MAP_SIZE - 1) as usize;
+    (uefi.BootServices.AllocatePages)(
+        1, // AllocateMaxAddress
+        MemoryType::EfiLoaderCode,
+        1,
+        &mut ptr
+    )?;
+
+    ptr::copy(CODE.as_ptr(), ptr as *mut u8, CODE.len());
+    TRAMPOLINE_PHYS = ptr as u64;
+    debug!("Trampoline {:X}", TRAMPOLINE_PHYS);
+
+    Ok(())
+}
+
+/// Load `page_phys` into CR3, switch to `stack`, and call `entry` with `args`, writing the
+/// markers of `CODE` to the serial port on the way
+///
+/// `stack` must be 16 byte aligned, and mapped by the new page tables.
+pub unsafe fn jump(page_phys: u64, stack: u64, entry: u64, args: u64) -> ! {
+    let trampoline: extern "sysv64" fn(u64, u64, u64, u64) -> ! = mem::transmute(TRAMPOLINE_PHYS);
+    trampoline(page_phys, stack, entry, args);
+}
@@ -0,0 +1,446 @@
+//! The graphical UI: splash, display mode menu, and text console
+
+use core::cmp;
+use core::ops::Try;
+use orbclient::{Color, Renderer};
+use std::string::String;
+use std::vec::Vec;
+use uefi::status::Result;
+
+use crate::chainload::chainload;
+use crate::config::{config, MenuAction};
+use crate::display::{Backend, Display, ScaledDisplay, Output};
+use crate::edit::edit_line;
+use crate::error::BootResult;
+use crate::font;
+use crate::fs::read_file;
+use crate::image::{self, Image};
+use crate::key::{key, Key};
+use crate::loaded_image::resolve;
+use crate::text::{text_area, TextDisplay};
+use crate::time;
+
+use super::{inner, BootFlags, CMDLINE, SPLASH_SHOWN_MS};
+
+static SPLASHBMP: &'static [u8] = include_bytes!("../../../res/splash.bmp");
+
+fn draw_text(display: &mut ScaledDisplay, mut x: i32, y: i32, text: &str, color: Color) {
+    for c in text.chars() {
+        font::draw(display, x, y, c, color);
+        x += 8;
+    }
+}
+
+/// The first row below the splash, where text may be drawn
+fn splash_bottom(display: &ScaledDisplay, splash: &Image) -> i32 {
+    let y = config().splash_y.resolve(display.height() as i32, splash.height() as i32);
+    y + splash.height() as i32 + 16
+}
+
+fn draw_background(display: &mut ScaledDisplay, splash: &Image) {
+    let config = config();
+
+    // Only fill our own regions when keeping the firmware's logo
+    let preserve = display.has_firmware_content();
+    if !preserve {
+        display.set(config.bg_color);
+    }
+
+    {
+        let x = config.splash_x.resolve(display.width() as i32, splash.width() as i32);
+        let y = config.splash_y.resolve(display.height() as i32, splash.height() as i32);
+        if preserve {
+            display.rect(x, y, splash.width(), splash.height(), config.bg_color);
+        }
+        splash.draw(display, x, y);
+        unsafe {
+            if SPLASH_SHOWN_MS.is_none() {
+                SPLASH_SHOWN_MS = Some(time::elapsed_ms());
+            }
+        }
+    }
+
+    {
+        let prompt = format!(
+            "Redox Bootloader {} {}",
+            env!("CARGO_PKG_VERSION"),
+            env!("TARGET").split('-').next().unwrap_or("")
+        );
+        let x = (display.width() as i32 - prompt.len() as i32 * 8)/2;
+        let y = display.height() as i32 - 32;
+        if preserve {
+            display.rect(x, y, prompt.len() as u32 * 8, 16, config.bg_color);
+        }
+        draw_text(display, x, y, &prompt, Color::rgb(0xff, 0xff, 0xff));
+    }
+}
+
+/// Set the first of `modes` that the firmware can set. Some firmware reports modes
+/// through QueryMode that it then fails to set.
+fn set_mode(backend: &mut Backend, modes: &[u32]) -> Result<()> {
+    fn try_set(output: &mut Output, mode: u32) -> Result<()> {
+        (output.0.SetMode)(output.0, mode)?;
+        Ok(())
+    }
+
+    let output = match backend {
+        Backend::Gop(output) => output,
+        _ => return Ok(()),
+    };
+
+    let mut result = Ok(());
+    for &mode in modes {
+        info!("Setting mode {}", mode);
+        match try_set(output, mode) {
+            Ok(()) => return Ok(()),
+            Err(err) => {
+                warn!("Failed to set mode {}: {:?}", mode, err);
+                result = Err(err);
+            }
+        }
+    }
+    result
+}
+
+/// The selected mode, then the next largest ones, then the mode the firmware started with
+fn mode_candidates(modes: &[(u32, u32, u32, String, u32)], selected: u32, current: u32) -> Vec<u32> {
+    let mut candidates = vec![selected];
+    candidates.extend(modes.iter().skip_while(|x| x.0 != selected).skip(1).map(|x| x.0));
+    if !candidates.contains(&current) {
+        candidates.push(current);
+    }
+    candidates
+}
+
+/// The mode that a typed number names, by its position in the list
+fn typed_mode(modes: &[(u32, u32, u32, String, u32)], number: &str) -> Option<u32> {
+    number.parse::<usize>().ok().and_then(|mode_i| modes.get(mode_i)).map(|x| x.0)
+}
+
+/// Select a display mode, returning what the user asked to do next
+fn select_mode(backend: &mut Backend, splash: &Image) -> Result<MenuAction> {
+    let output = match backend {
+        Backend::Gop(output) => output,
+        // Only GOP supports changing modes
+        _ => return Ok(MenuAction::Boot),
+    };
+
+    // Read all available modes
+    let mut modes = Vec::new();
+    for i in 0..output.0.Mode.MaxMode {
+        let mut mode_ptr = ::core::ptr::null_mut();
+        let mut mode_size = 0;
+        // Some firmware leaves holes in the mode list, skip them
+        if (output.0.QueryMode)(output.0, i, &mut mode_size, &mut mode_ptr).branch().is_break() {
+            continue;
+        }
+
+        let mode = unsafe { &mut *mode_ptr };
+        let w = mode.HorizontalResolution;
+        let h = mode.VerticalResolution;
+
+        let mut aspect_w = w;
+        let mut aspect_h = h;
+        for i in 2..cmp::min(aspect_w / 2, aspect_h / 2) {
+            while aspect_w % i == 0 && aspect_h % i == 0 {
+                aspect_w /= i;
+                aspect_h /= i;
+            }
+        }
+
+        //TODO: support resolutions that are not perfect multiples of 4
+        if w % 4 != 0 {
+            continue;
+        }
+
+        modes.push((i, w, h, format!("{:>4}x{:<4} {:>3}:{:<3}", w, h, aspect_w, aspect_h), mode.PixelFormat));
+    }
+
+    // Only offer modes with the configured pixel format, unless there are none
+    if let Some(format) = config().pixel_format {
+        if modes.iter().any(|x| x.4 == format as u32) {
+            modes.retain(|x| x.4 == format as u32);
+        } else {
+            warn!("No mode has pixel format {:?}, using the default", format);
+        }
+    }
+
+    // Sort modes by pixel area, reversed
+    modes.sort_by(|a, b| (b.1 * b.2).cmp(&(a.1 * a.2)));
+
+    // Default to the current mode, which the firmware and monitor already agreed on
+    let current = output.0.Mode.Mode;
+    let mut selected = current;
+
+    // If there are no modes from querymode, don't change mode
+    if modes.is_empty() {
+        return Ok(MenuAction::Boot);
+    }
+
+    // The current mode may have been skipped above, fall back to the largest one
+    if !modes.iter().any(|x| x.0 == selected) {
+        selected = modes[0].0;
+    }
+
+    let white = Color::rgb(0xff, 0xff, 0xff);
+    let black = Color::rgb(0x00, 0x00, 0x00);
+    let rows = 12;
+    // Digits typed so far to pick a mode by its number
+    let mut number = String::new();
+    loop {
+        {
+            // Create a scaled display
+            let mut display = Display::new(backend);
+            let mut display = ScaledDisplay::new(&mut display);
+
+            draw_background(&mut display, splash);
+
+            let off_x = (display.width() as i32 - 60 * 8)/2;
+            let mut off_y = splash_bottom(&display, splash);
+            if display.has_firmware_content() {
+                // Hints, typed number, chainload and hotkey entries, and the mode list
+                let w = display.width();
+                display.rect(0, off_y, w, 40 + 32 + rows as u32 * 16, config().bg_color);
+            }
+            draw_text(
+                &mut display,
+                off_x, off_y,
+                "Arrows or number and enter select mode, e edits cmdline, F8 safe mode",
+                white
+            );
+            if let Some(path) = &config().chainload {
+                draw_text(&mut display, off_x, off_y + 16, &format!("c chainloads {}", path), white);
+                off_y += 16;
+            }
+            if !config().hotkeys.is_empty() {
+                let hotkeys: Vec<String> = config().hotkeys.iter()
+                    .map(|(c, action)| format!("{} {}", c, action.name()))
+                    .collect();
+                draw_text(&mut display, off_x, off_y + 16, &format!("Hotkeys: {}", hotkeys.join(", ")), white);
+                off_y += 16;
+            }
+            if !number.is_empty() {
+                let text = if typed_mode(&modes, &number).is_some() {
+                    format!("Mode: {}", number)
+                } else {
+                    format!("Mode: {}, no such mode", number)
+                };
+                draw_text(&mut display, off_x, off_y + 16, &text, white);
+            }
+            off_y += 40;
+
+            let mut row = 0;
+            let mut col = 0;
+            for (mode_i, (i, _w, _h, text, _format)) in modes.iter().enumerate() {
+                if row >= rows as i32 {
+                    col += 1;
+                    row = 0;
+                }
+
+                let x = off_x + col * 24 * 8;
+                let y = off_y + row * 16;
+                let text = format!("{:>2}: {}{}", mode_i, text, if *i == current { "*" } else { "" });
+
+                let fg = if *i == selected {
+                    display.rect(x - 8, y, text.len() as u32 * 8 + 16, 16, white);
+                    black
+                } else {
+                    white
+                };
+
+                draw_text(&mut display, x, y, &text, fg);
+
+                row += 1;
+            }
+
+            display.sync();
+        }
+
+        let pressed = key(true)?;
+        let hotkey = match pressed {
+            Key::Character(c) => config().hotkeys.iter().find(|&&(key, _)| key == c).map(|&(_, action)| action),
+            _ => None,
+        };
+        match hotkey {
+            // Chainloading needs a path
+            Some(MenuAction::Chainload) if config().chainload.is_none() => (),
+            // Like their keys below, only boot and editing the cmdline set the selected mode
+            Some(action @ MenuAction::SafeMode) | Some(action @ MenuAction::Chainload) => return Ok(action),
+            Some(action) => {
+                set_mode(backend, &mode_candidates(&modes, selected, current))?;
+                return Ok(action);
+            },
+            None => (),
+        }
+
+        match pressed {
+            Key::Left => {
+                number.clear();
+                if let Some(mut mode_i) = modes.iter().position(|x| x.0 == selected) {
+                    if mode_i < rows {
+                        while mode_i < modes.len() {
+                            mode_i += rows;
+                        }
+                    }
+                    mode_i -= rows;
+                    if let Some(new) = modes.get(mode_i) {
+                        selected = new.0;
+                    }
+                }
+            },
+            Key::Right => {
+                number.clear();
+                if let Some(mut mode_i) = modes.iter().position(|x| x.0 == selected) {
+                    mode_i += rows;
+                    if mode_i >= modes.len() {
+                        mode_i = mode_i % rows;
+                    }
+                    if let Some(new) = modes.get(mode_i) {
+                        selected = new.0;
+                    }
+                }
+            },
+            Key::Up => {
+                number.clear();
+                if let Some(mut mode_i) = modes.iter().position(|x| x.0 == selected) {
+                    if mode_i % rows == 0 {
+                        mode_i += rows;
+                        if mode_i > modes.len() {
+                            mode_i = modes.len();
+                        }
+                    }
+                    mode_i -= 1;
+                    if let Some(new) = modes.get(mode_i) {
+                        selected = new.0;
+                    }
+                }
+            },
+            Key::Down => {
+                number.clear();
+                if let Some(mut mode_i) = modes.iter().position(|x| x.0 == selected) {
+                    mode_i += 1;
+                    if mode_i % rows == 0 {
+                        mode_i -= rows;
+                    }
+                    if mode_i >= modes.len() {
+                        mode_i = mode_i - mode_i % rows;
+                    }
+                    if let Some(new) = modes.get(mode_i) {
+                        selected = new.0;
+                    }
+                }
+            },
+            // A typed number that names no mode must not boot the highlighted one instead
+            Key::Enter | Key::Character('e') if !number.is_empty() && typed_mode(&modes, &number).is_none() => (),
+            Key::Enter => {
+                set_mode(backend, &mode_candidates(&modes, selected, current))?;
+                return Ok(MenuAction::Boot);
+            },
+            Key::Character('e') => {
+                set_mode(backend, &mode_candidates(&modes, selected, current))?;
+                return Ok(MenuAction::EditCmdline);
+            },
+            // Safe mode keeps the firmware's resolution
+            Key::F8 => return Ok(MenuAction::SafeMode),
+            Key::Character('c') if config().chainload.is_some() => return Ok(MenuAction::Chainload),
+            Key::Character(c) if c.is_ascii_digit() => {
+                if number.len() < 3 {
+                    number.push(c);
+                }
+                // Highlight the typed mode so enter picks it
+                if let Some(new) = typed_mode(&modes, &number) {
+                    selected = new;
+                }
+            },
+            Key::Backspace => {
+                number.pop();
+                if let Some(new) = typed_mode(&modes, &number) {
+                    selected = new;
+                }
+            },
+            _ => (),
+        }
+    }
+}
+
+fn pretty_pipe<T, F: FnMut() -> Result<T>>(backend: &mut Backend, splash: &Image, f: F) -> Result<T> {
+    let mut display = Display::new(backend);
+
+    let mut display = ScaledDisplay::new(&mut display);
+
+    draw_background(&mut display, splash);
+
+    display.sync();
+
+    {
+        let off_y = splash_bottom(&display, splash);
+        let (off_x, cols, rows) = text_area(display.width(), display.height(), off_y);
+        display.rect(off_x, off_y, cols as u32 * 8, rows as u32 * 16, Color::rgb(0, 0, 0));
+        display.sync();
+
+        let mut text = TextDisplay::new(display);
+        text.off_x = off_x;
+        text.off_y = off_y;
+        text.cols = cols;
+        text.rows = rows;
+        text.pipe(f)
+    }
+}
+
+/// Load the splash from the ESP if there is one, so it can be themed without rebuilding,
+/// falling back to the built in one
+fn load_splash() -> Image {
+    println!("Loading Splash...");
+
+    for name in ["splash.png", "splash.bmp"].iter() {
+        let path = resolve(name);
+        if let Ok(data) = read_file(&path) {
+            let result = if name.ends_with(".png") {
+                image::png::parse(&data)
+            } else {
+                image::bmp::parse(&data)
+            };
+            match result {
+                Ok(image) => {
+                    println!(" Done, using {}", path);
+                    return image;
+                },
+                Err(err) => println!("Failed to parse {}: {}", path, err),
+            }
+        }
+    }
+
+    let splash = image::bmp::parse(&SPLASHBMP).unwrap_or_else(|_| Image::new(0, 0));
+    println!(" Done");
+    splash
+}
+
+/// Boot with the splash, mode menu, and text console drawn on `backend`
+pub fn main(backend: &mut Backend, safe_mode: bool) -> BootResult<()> {
+    let splash = load_splash();
+
+    // Safe mode also skips choosing a resolution
+    let action = if safe_mode {
+        MenuAction::SafeMode
+    } else {
+        select_mode(backend, &splash)?
+    };
+    if action == MenuAction::Chainload {
+        if let Some(path) = &config().chainload {
+            chainload(path)?;
+        }
+    }
+
+    let flags = BootFlags::new(action == MenuAction::SafeMode);
+
+    pretty_pipe(backend, &splash, || {
+        if action == MenuAction::EditCmdline {
+            if let Some(cmdline) = edit_line("cmdline: ", &config().cmdline)? {
+                unsafe { CMDLINE = Some(cmdline); }
+            }
+        }
+        Ok(inner(flags))
+    })??;
+
+    Ok(())
+}
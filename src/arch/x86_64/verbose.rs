@@ -0,0 +1,152 @@
+//! Firmware diagnostics printed before loading when `verbose` is set
+
+use std::proto::Protocol;
+use std::string::String;
+use uefi::status::Result;
+
+use crate::device_path::guid_string;
+use crate::disk::DiskEfi;
+use crate::display::Output;
+use crate::key::key;
+use crate::proto::locate_handles;
+
+use super::memory_map;
+use super::partitions::{PartitionProto, PartitionProtoDataTy};
+
+/// Names of the firmware memory types, by their raw value
+static MEMORY_TYPE_NAMES: [&str; 16] = [
+    "Reserved",
+    "LoaderCode",
+    "LoaderData",
+    "BootServicesCode",
+    "BootServicesData",
+    "RuntimeServicesCode",
+    "RuntimeServicesData",
+    "Conventional",
+    "Unusable",
+    "AcpiReclaim",
+    "AcpiNvs",
+    "MemoryMappedIo",
+    "MemoryMappedIoPortSpace",
+    "PalCode",
+    "Persistent",
+    "Unaccepted",
+];
+
+/// Prints lines, waiting for a key whenever the screen is full
+struct Pager {
+    line: usize,
+    rows: usize,
+}
+
+impl Pager {
+    fn new() -> Self {
+        let output = std::system_table().ConsoleOut;
+        let mut cols = 0;
+        let mut rows = 0;
+        let _ = (output.QueryMode)(output, output.Mode.Mode as usize, &mut cols, &mut rows);
+        Self {
+            line: 0,
+            // Leave a row for the prompt
+            rows: if rows > 1 { rows - 1 } else { 24 },
+        }
+    }
+
+    fn println(&mut self, line: String) -> Result<()> {
+        if self.line >= self.rows {
+            print!("-- Press any key --");
+            key(true)?;
+            print!("\r                   \r");
+            self.line = 0;
+        }
+        println!("{}", line);
+        self.line += 1;
+        Ok(())
+    }
+}
+
+fn block_io_handles(pager: &mut Pager) -> Result<()> {
+    let handles = locate_handles(&uefi::guid::BLOCK_IO_GUID)?;
+
+    pager.println(format!("BlockIo handles: {}", handles.len()))?;
+    for handle in handles {
+        let block_io = match DiskEfi::handle_protocol(handle) {
+            Ok(block_io) => block_io,
+            Err(err) => {
+                pager.println(format!("  {:X}: {:?}", handle.0, err))?;
+                continue;
+            }
+        };
+        let media = &block_io.0.Media;
+        let mut line = format!(
+            "  {:X}: {} blocks of {}{}",
+            handle.0,
+            media.LastBlock + 1,
+            media.BlockSize,
+            if media.LogicalPartition { " partition" } else { "" }
+        );
+
+        if let Ok(part) = PartitionProto::handle_protocol(handle) {
+            let part = part.0;
+            if part.ty == PartitionProtoDataTy::Gpt as u32 {
+                let gpt = unsafe { part.info.gpt };
+                line.push_str(&format!(" GPT type {} uuid {}", guid_string(&gpt.part_ty_guid), guid_string(&gpt.uniq_guid)));
+            } else if part.ty == PartitionProtoDataTy::Mbr as u32 {
+                let mbr = unsafe { part.info.mbr };
+                line.push_str(&format!(" MBR type {:02X}", mbr.ty));
+            }
+            if part.sys == 1 {
+                line.push_str(" ESP");
+            }
+        }
+
+        pager.println(line)?;
+    }
+    Ok(())
+}
+
+fn gop_modes(pager: &mut Pager) -> Result<()> {
+    let output = match Output::one() {
+        Ok(output) => output,
+        Err(_) => return pager.println(String::from("GOP: not available")),
+    };
+
+    pager.println(format!("GOP modes: {}, current {}", output.0.Mode.MaxMode, output.0.Mode.Mode))?;
+    for i in 0..output.0.Mode.MaxMode {
+        let mut mode_ptr = ::core::ptr::null_mut();
+        let mut mode_size = 0;
+        (output.0.QueryMode)(output.0, i, &mut mode_size, &mut mode_ptr)?;
+        let mode = unsafe { &*mode_ptr };
+        pager.println(format!("  {}: {}x{}", i, mode.HorizontalResolution, mode.VerticalResolution))?;
+    }
+    Ok(())
+}
+
+fn config_tables(pager: &mut Pager) -> Result<()> {
+    let cfg_tables = std::system_table().config_tables();
+    pager.println(format!("Config tables: {}", cfg_tables.len()))?;
+    for cfg_table in cfg_tables.iter() {
+        pager.println(format!("  {:?} at {:X}", cfg_table.VendorGuid.kind(), cfg_table.VendorTable))?;
+    }
+    Ok(())
+}
+
+fn memory_summary(pager: &mut Pager) -> Result<()> {
+    pager.println(String::from("Memory map:"))?;
+    for (ty, pages) in memory_map::pages_by_type().iter().enumerate() {
+        if *pages > 0 {
+            pager.println(format!("  {:<24} {} KB", MEMORY_TYPE_NAMES[ty], pages * 4))?;
+        }
+    }
+    Ok(())
+}
+
+/// Print block devices, display modes, config tables, and memory usage
+pub fn dump() -> Result<()> {
+    let mut pager = Pager::new();
+    block_io_handles(&mut pager)?;
+    gop_modes(&mut pager)?;
+    config_tables(&mut pager)?;
+    memory_summary(&mut pager)?;
+    Ok(())
+}
@@ -0,0 +1,50 @@
+//! Hand off to another EFI application, such as the bootloader of another OS
+
+use core::ptr;
+use std::proto::Protocol;
+use uefi::Handle;
+use uefi::status::{Error, Result};
+
+use crate::device_path::{self, DevicePath};
+use crate::fs::read_file;
+use crate::loaded_image::LoadedImage;
+use crate::watchdog;
+
+/// Load and start the EFI application at `path` on the ESP
+///
+/// Boot services stay up, since the application needs them. This only returns if the
+/// application fails to start or exits, with the watchdog running again for booting Redox.
+pub fn chainload(path: &str) -> Result<()> {
+    let uefi = std::system_table();
+
+    println!("Chainloading {}", path);
+    let data = read_file(path)?;
+
+    // Give the image a device path on the ESP, so it can find its own files
+    let image = LoadedImage::current().ok_or(Error::NotFound)?;
+    let device = DevicePath::handle_protocol(image.0.DeviceHandle)?;
+    let file_path = device_path::with_file_path(&device, path);
+
+    let mut handle = Handle(0);
+    (uefi.BootServices.LoadImage)(
+        false,
+        std::handle(),
+        file_path.as_ptr() as usize,
+        data.as_ptr(),
+        data.len(),
+        &mut handle
+    )?;
+
+    // The timeout is for booting Redox, the firmware arms its own for the application
+    watchdog::suspend();
+
+    let mut exit_data_size = 0;
+    let mut exit_data = ptr::null_mut();
+    let status = (uefi.BootServices.StartImage)(handle, &mut exit_data_size, &mut exit_data);
+
+    // The application returned, so booting Redox goes on under the watchdog
+    watchdog::resume();
+
+    status?;
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use std::proto::Protocol;
+use std::vec::Vec;
+use uefi::guid::Guid;
+use uefi::status::{Error, Result, Status};
+use util::compress::{gunzip, is_efi_compressed, GzipError, GZIP_MAGIC, ZSTD_MAGIC};
+
+pub use util::compress::has_magic;
+
+#[repr(C)]
+#[allow(non_snake_case)]
+pub struct DecompressData {
+    pub GetInfo: extern "win64" fn(&DecompressData, *const u8, u32, &mut u32, &mut u32) -> Status,
+    pub Decompress: extern "win64" fn(&DecompressData, *const u8, u32, *mut u8, u32, *mut u8, u32) -> Status,
+}
+
+/// EFI_DECOMPRESS_PROTOCOL, for the UEFI compression format
+pub struct Decompress(pub &'static mut DecompressData);
+
+impl Protocol<DecompressData> for Decompress {
+    fn guid() -> Guid {
+        Guid(0xd8117cfe, 0x94a6, 0x11d4, [0x9a, 0x3a, 0x00, 0x90, 0x27, 0x3f, 0xc1, 0x4d])
+    }
+
+    fn new(inner: &'static mut DecompressData) -> Self {
+        Self(inner)
+    }
+}
+
+fn gzip(data: &[u8]) -> Result<Vec<u8>> {
+    gunzip(data).map_err(|err| {
+        match err {
+            GzipError::Header => warn!("Invalid gzip header"),
+            GzipError::Inflate(status) => warn!("Failed to inflate gzip data: {:?}", status),
+        }
+        Error::LoadError
+    })
+}
+
+/// Decompress with the firmware, which must happen before boot services exit
+fn efi(data: &[u8]) -> Result<Vec<u8>> {
+    let decompress = Decompress::one().map_err(|err| {
+        warn!("Firmware does not support UEFI decompression");
+        err
+    })?;
+
+    let mut destination_size = 0;
+    let mut scratch_size = 0;
+    (decompress.0.GetInfo)(decompress.0, data.as_ptr(), data.len() as u32, &mut destination_size, &mut scratch_size)?;
+
+    let mut output = vec![0; destination_size as usize];
+    let mut scratch = vec![0; scratch_size as usize];
+    (decompress.0.Decompress)(
+        decompress.0,
+        data.as_ptr(),
+        data.len() as u32,
+        output.as_mut_ptr(),
+        destination_size,
+        scratch.as_mut_ptr(),
+        scratch_size
+    )?;
+
+    Ok(output)
+}
+
+/// Decompress `data` if it starts with a known magic, or return `None` if it is raw
+pub fn maybe_decompress(data: &[u8]) -> Result<Option<Vec<u8>>> {
+    if data.starts_with(&GZIP_MAGIC) {
+        println!("Decompressing gzip");
+        gzip(data).map(Some)
+    } else if data.starts_with(&ZSTD_MAGIC) {
+        // No zstd decoder builds for no_std with this toolchain
+        println!("zstd compressed kernels are not supported, compress the kernel with gzip");
+        Err(Error::Unsupported)
+    } else if is_efi_compressed(data) {
+        // Only the sizes are checked, so a raw kernel can look compressed by chance
+        println!("Decompressing with the firmware");
+        match efi(data) {
+            Ok(output) => Ok(Some(output)),
+            Err(err) => {
+                warn!("UEFI decompression failed: {:?}, booting the kernel as it is", err);
+                Ok(None)
+            }
+        }
+    } else {
+        Ok(None)
+    }
+}
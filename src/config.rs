@@ -0,0 +1,491 @@
+use core::str;
+use orbclient::Color;
+use std::string::{String, ToString};
+use std::vec::Vec;
+
+use crate::fs::read_file;
+use crate::loaded_image::resolve;
+
+static mut CONFIG: Option<Config> = None;
+
+/// A coordinate that is either fixed or centered in the available space
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Position {
+    Center,
+    Fixed(i32),
+}
+
+impl Position {
+    /// Resolve to a coordinate for an item of `size` in `available` space
+    pub fn resolve(&self, available: i32, size: i32) -> i32 {
+        match *self {
+            Position::Center => (available - size)/2,
+            Position::Fixed(value) => value,
+        }
+    }
+}
+
+/// Which display backend to use
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DisplayBackend {
+    /// The best one the firmware supports
+    Auto,
+    /// Discard all drawing, for automated testing
+    Null,
+    /// Firmware text console only
+    Text,
+}
+
+/// Where to look for the kernel when it is not a URL
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KernelSource {
+    /// The ESP, then RedoxFS
+    Auto,
+    Esp,
+    Redoxfs,
+}
+
+/// How the kernel file is laid out
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KernelFormat {
+    /// ELF if the file starts with the ELF magic, flat if `flat_entry` or `flat_load` is set
+    /// and the file is not compressed
+    Auto,
+    Elf,
+    /// A raw binary, loaded at `flat_load` and entered at `flat_entry`
+    Flat,
+}
+
+/// Layout of the keyboard, used when typing at boot
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyboardLayout {
+    Us,
+    De,
+    Fr,
+}
+
+/// Pixel format of a GOP mode, with the values of `EFI_GRAPHICS_PIXEL_FORMAT`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PixelFormat {
+    Rgbx = 0,
+    Bgrx = 1,
+}
+
+/// Where messages are written
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Console {
+    /// Serial port only, without drawing anything on the screen
+    Serial,
+    /// Firmware console or graphics only
+    Screen,
+    /// The screen, and log messages also to the serial port
+    Both,
+}
+
+/// What to do after the display mode menu
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MenuAction {
+    Boot,
+    EditCmdline,
+    SafeMode,
+    Chainload,
+}
+
+#[cfg_attr(feature = "minimal", allow(dead_code))]
+impl MenuAction {
+    /// Name used by the `hotkey` key and shown in the menu
+    pub fn name(&self) -> &'static str {
+        match *self {
+            MenuAction::Boot => "boot",
+            MenuAction::EditCmdline => "cmdline",
+            MenuAction::SafeMode => "safe_mode",
+            MenuAction::Chainload => "chainload",
+        }
+    }
+}
+
+/// Most detailed messages that are shown, see `log`
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+pub struct Config {
+    pub bg_color: Color,
+    pub splash_x: Position,
+    pub splash_y: Position,
+    pub cmdline: String,
+    pub watchdog: usize,
+    pub load_addr: Option<u64>,
+    pub kernel: String,
+    pub debug: bool,
+    pub fine_grained_paging: bool,
+    pub stack_size: u64,
+    pub crc32_strict: bool,
+    pub display: DisplayBackend,
+    pub verbose: bool,
+    pub kernel_source: KernelSource,
+    pub keyboard_layout: KeyboardLayout,
+    pub safe_mode: bool,
+    pub phys_offset: Option<u64>,
+    pub max_kernel_size: u64,
+    /// Extra files passed to the kernel, from repeated `module` keys
+    pub modules: Vec<String>,
+    pub log_level: LogLevel,
+    /// Absolute path of an EFI application on the ESP, offered in the menu, or started by
+    /// holding `c` while starting on x86_64
+    pub chainload: Option<String>,
+    /// Keep what the firmware drew, such as a vendor logo, and only draw over our own regions
+    pub preserve_firmware_logo: bool,
+    /// Copy all ACPI tables for the kernel, not only the RSDPs
+    pub acpi_copy_tables: bool,
+    /// Prefer display modes with this pixel format
+    pub pixel_format: Option<PixelFormat>,
+    /// Read ahead from disks for filesystem metadata, which uses an extra 256 KiB buffer
+    pub read_prefetch: bool,
+    /// Wait for a key between boot stages, on the serial port after boot services exit
+    pub step: bool,
+    /// Read the kernel back after copying or reading it from disk into kernel memory, to catch
+    /// memory that does not hold writes. Disk reads go through an extra 4 MB buffer.
+    pub verify_copy: bool,
+    /// Extra `KEY=VALUE` lines for the kernel environment, from repeated `env` keys
+    pub env: Vec<String>,
+    /// Least time the splash is shown before the kernel takes over the screen
+    pub splash_min_ms: u64,
+    /// Refuse kernels built for another bootloader ABI, instead of only warning
+    pub abi_strict: bool,
+    /// Where messages are written. The serial port is only driven on x86_64, so on aarch64
+    /// `serial` only turns off graphics, and `both` is the same as `screen`.
+    pub console: Console,
+    /// Map the framebuffer write-combining for the kernel, which reprograms the PAT
+    pub framebuffer_wc: bool,
+    /// Pattern test free memory before loading the kernel
+    pub memtest: bool,
+    /// Replace or add ACPI tables with the `acpi\*.aml` files on the ESP, this implies
+    /// `acpi_copy_tables`
+    pub acpi_override: bool,
+    /// Keys that pick a menu action right away, from repeated `hotkey` keys
+    pub hotkeys: Vec<(char, MenuAction)>,
+    /// Boot from this disk instead of scanning, counting from zero in device path order
+    pub boot_disk: Option<usize>,
+    /// Boot from this partition of `boot_disk` instead of scanning, counting from zero
+    pub boot_partition: Option<usize>,
+    pub kernel_format: KernelFormat,
+    /// Offset of the entry point from the start of a flat kernel, zero if not set
+    pub flat_entry: Option<u64>,
+    /// Physical address a flat kernel is loaded at, instead of `load_addr`
+    pub flat_load: Option<u64>,
+    /// Move the kernel to a 1 GiB aligned base and map it with 1 GiB pages, or 2 MiB pages
+    /// if the CPU has no 1 GiB pages
+    pub kernel_huge_pages: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bg_color: Color::rgb(0x4a, 0xa3, 0xfd),
+            splash_x: Position::Center,
+            splash_y: Position::Fixed(16),
+            cmdline: String::new(),
+            watchdog: 120,
+            load_addr: None,
+            kernel: "kernel".to_string(),
+            debug: false,
+            fine_grained_paging: false,
+            stack_size: 0x20000,
+            crc32_strict: true,
+            display: DisplayBackend::Auto,
+            verbose: false,
+            kernel_source: KernelSource::Auto,
+            keyboard_layout: KeyboardLayout::Us,
+            safe_mode: false,
+            phys_offset: None,
+            max_kernel_size: 256 * 1024 * 1024,
+            modules: Vec::new(),
+            log_level: LogLevel::Info,
+            chainload: None,
+            preserve_firmware_logo: false,
+            acpi_copy_tables: false,
+            pixel_format: None,
+            read_prefetch: false,
+            step: false,
+            verify_copy: false,
+            env: Vec::new(),
+            splash_min_ms: 0,
+            abi_strict: false,
+            console: Console::Both,
+            framebuffer_wc: false,
+            memtest: false,
+            acpi_override: false,
+            hotkeys: Vec::new(),
+            boot_disk: None,
+            boot_partition: None,
+            kernel_format: KernelFormat::Auto,
+            flat_entry: None,
+            flat_load: None,
+            kernel_huge_pages: false,
+        }
+    }
+}
+
+impl Config {
+    /// Parse `key = value` lines, ignoring blank lines and `#` comments
+    pub fn parse(data: &str) -> Self {
+        let mut config = Self::default();
+
+        for line in data.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next().unwrap_or("").trim();
+            let value = match parts.next() {
+                Some(value) => value.trim(),
+                None => {
+                    println!("Config: missing value for {}", key);
+                    continue;
+                }
+            };
+
+            if let Err(err) = config.set(key, value) {
+                println!("Config: {} = {}: {}", key, value, err);
+            }
+        }
+
+        config
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), &'static str> {
+        match key {
+            "bg_color" => self.bg_color = parse_color(value)?,
+            "splash_x" => self.splash_x = parse_position(value)?,
+            "splash_y" => self.splash_y = parse_position(value)?,
+            "cmdline" => self.cmdline = value.to_string(),
+            "watchdog" => self.watchdog = value.parse().map_err(|_| "expected seconds")?,
+            "load_addr" => self.load_addr = Some(parse_hex(value)?),
+            "kernel" => self.kernel = value.to_string(),
+            "debug" => self.debug = parse_bool(value)?,
+            "fine_grained_paging" => self.fine_grained_paging = parse_bool(value)?,
+            "stack_size" => self.stack_size = parse_size(value)?,
+            "crc32_strict" => self.crc32_strict = parse_bool(value)?,
+            "display" => self.display = parse_display(value)?,
+            "verbose" => self.verbose = parse_bool(value)?,
+            "kernel_source" => self.kernel_source = parse_kernel_source(value)?,
+            "keyboard_layout" => self.keyboard_layout = parse_keyboard_layout(value)?,
+            "safe_mode" => self.safe_mode = parse_bool(value)?,
+            "phys_offset" => self.phys_offset = Some(parse_hex(value)?),
+            "max_kernel_size" => self.max_kernel_size = parse_size(value)?,
+            "module" => self.modules.push(value.to_string()),
+            "log_level" => self.log_level = parse_log_level(value)?,
+            "preserve_firmware_logo" => self.preserve_firmware_logo = parse_bool(value)?,
+            "acpi_copy_tables" => self.acpi_copy_tables = parse_bool(value)?,
+            "pixel_format" => self.pixel_format = Some(parse_pixel_format(value)?),
+            "read_prefetch" => self.read_prefetch = parse_bool(value)?,
+            "step" => self.step = parse_bool(value)?,
+            "verify_copy" => self.verify_copy = parse_bool(value)?,
+            "env" => self.env.push(parse_env(value)?),
+            "splash_min_ms" => self.splash_min_ms = value.parse().map_err(|_| "expected milliseconds")?,
+            "abi_strict" => self.abi_strict = parse_bool(value)?,
+            "console" => self.console = parse_console(value)?,
+            "framebuffer_wc" => self.framebuffer_wc = parse_bool(value)?,
+            "memtest" => self.memtest = parse_bool(value)?,
+            "acpi_override" => self.acpi_override = parse_bool(value)?,
+            "hotkey" => {
+                let (c, action) = parse_hotkey(value)?;
+                // The first one configured wins
+                if self.hotkeys.iter().any(|&(key, _)| key == c) {
+                    return Err("key is already a hotkey");
+                }
+                self.hotkeys.push((c, action));
+            },
+            "boot_disk" => self.boot_disk = Some(value.parse().map_err(|_| "expected a disk number")?),
+            "boot_partition" => self.boot_partition = Some(value.parse().map_err(|_| "expected a partition number")?),
+            "kernel_format" => self.kernel_format = parse_kernel_format(value)?,
+            "flat_entry" => self.flat_entry = Some(parse_hex(value)?),
+            "flat_load" => self.flat_load = Some(parse_hex(value)?),
+            "kernel_huge_pages" => self.kernel_huge_pages = parse_bool(value)?,
+            "chainload" => self.chainload = Some(value.replace('/', "\\")),
+            _ => return Err("unknown key"),
+        }
+        Ok(())
+    }
+
+    /// Check if the kernel is fetched from the network instead of a disk
+    pub fn kernel_is_url(&self) -> bool {
+        self.kernel.contains("://")
+    }
+
+    /// The kernel path on the ESP, relative to the bootloader directory
+    pub fn kernel_esp_path(&self) -> String {
+        esp_path(&self.kernel)
+    }
+}
+
+/// Convert a configured path to one on the ESP, relative to the bootloader directory
+pub fn esp_path(path: &str) -> String {
+    resolve(&path.trim_start_matches('/').replace('/', "\\"))
+}
+
+fn parse_color(value: &str) -> Result<Color, &'static str> {
+    let value = value.trim_start_matches('#');
+    if value.len() != 6 {
+        return Err("expected RRGGBB");
+    }
+    let rgb = u32::from_str_radix(value, 16).map_err(|_| "expected RRGGBB")?;
+    Ok(Color::rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+}
+
+fn parse_bool(value: &str) -> Result<bool, &'static str> {
+    match value {
+        "true" | "yes" | "1" => Ok(true),
+        "false" | "no" | "0" => Ok(false),
+        _ => Err("expected true or false"),
+    }
+}
+
+fn parse_hex(value: &str) -> Result<u64, &'static str> {
+    let digits = value.trim_start_matches("0x").trim_start_matches("0X");
+    u64::from_str_radix(digits, 16).map_err(|_| "expected a hexadecimal number")
+}
+
+/// Parse a size in bytes, in hexadecimal with a `0x` prefix or decimal
+fn parse_size(value: &str) -> Result<u64, &'static str> {
+    if value.starts_with("0x") || value.starts_with("0X") {
+        parse_hex(value)
+    } else {
+        value.parse().map_err(|_| "expected a size in bytes")
+    }
+}
+
+fn parse_console(value: &str) -> Result<Console, &'static str> {
+    match value {
+        "serial" => Ok(Console::Serial),
+        "screen" => Ok(Console::Screen),
+        "both" => Ok(Console::Both),
+        _ => Err("expected serial, screen, or both"),
+    }
+}
+
+/// Parse `action:key`, where the key is one character that is not a digit
+fn parse_hotkey(value: &str) -> Result<(char, MenuAction), &'static str> {
+    let mut parts = value.splitn(2, ':');
+    let action = match parts.next().unwrap_or("").trim() {
+        "boot" => MenuAction::Boot,
+        "cmdline" => MenuAction::EditCmdline,
+        "safe_mode" => MenuAction::SafeMode,
+        "chainload" => MenuAction::Chainload,
+        _ => return Err("expected boot, cmdline, safe_mode, or chainload"),
+    };
+
+    let mut chars = parts.next().unwrap_or("").trim().chars();
+    match (chars.next(), chars.next()) {
+        // Digits are used to type mode numbers
+        (Some(c), None) if !c.is_ascii_digit() => Ok((c, action)),
+        _ => Err("expected action:key with a single non-digit key"),
+    }
+}
+
+fn parse_display(value: &str) -> Result<DisplayBackend, &'static str> {
+    match value {
+        "auto" => Ok(DisplayBackend::Auto),
+        "null" => Ok(DisplayBackend::Null),
+        "text" => Ok(DisplayBackend::Text),
+        _ => Err("expected auto, null, or text"),
+    }
+}
+
+fn parse_kernel_source(value: &str) -> Result<KernelSource, &'static str> {
+    match value {
+        "auto" => Ok(KernelSource::Auto),
+        "esp" => Ok(KernelSource::Esp),
+        "redoxfs" => Ok(KernelSource::Redoxfs),
+        _ => Err("expected auto, esp, or redoxfs"),
+    }
+}
+
+fn parse_kernel_format(value: &str) -> Result<KernelFormat, &'static str> {
+    match value {
+        "auto" => Ok(KernelFormat::Auto),
+        "elf" => Ok(KernelFormat::Elf),
+        "flat" => Ok(KernelFormat::Flat),
+        _ => Err("expected auto, elf, or flat"),
+    }
+}
+
+fn parse_keyboard_layout(value: &str) -> Result<KeyboardLayout, &'static str> {
+    match value {
+        "us" => Ok(KeyboardLayout::Us),
+        "de" => Ok(KeyboardLayout::De),
+        "fr" => Ok(KeyboardLayout::Fr),
+        _ => Err("expected us, de, or fr"),
+    }
+}
+
+fn parse_env(value: &str) -> Result<String, &'static str> {
+    match value.find('=') {
+        Some(i) if i > 0 => Ok(value.to_string()),
+        _ => Err("expected KEY=VALUE"),
+    }
+}
+
+fn parse_log_level(value: &str) -> Result<LogLevel, &'static str> {
+    match value {
+        "error" => Ok(LogLevel::Error),
+        "warn" => Ok(LogLevel::Warn),
+        "info" => Ok(LogLevel::Info),
+        "debug" => Ok(LogLevel::Debug),
+        _ => Err("expected error, warn, info, or debug"),
+    }
+}
+
+fn parse_pixel_format(value: &str) -> Result<PixelFormat, &'static str> {
+    match value {
+        "rgbx" => Ok(PixelFormat::Rgbx),
+        "bgrx" => Ok(PixelFormat::Bgrx),
+        _ => Err("expected rgbx or bgrx"),
+    }
+}
+
+fn parse_position(value: &str) -> Result<Position, &'static str> {
+    if value == "center" {
+        Ok(Position::Center)
+    } else {
+        value.parse::<i32>()
+            .map(Position::Fixed)
+            .map_err(|_| "expected a number or center")
+    }
+}
+
+/// Load the configuration file, falling back to defaults if it is missing
+pub fn load() {
+    let path = resolve("bootloader.conf");
+    let config = match read_file(&path) {
+        Ok(data) => match str::from_utf8(&data) {
+            Ok(data) => Config::parse(data),
+            Err(_) => {
+                println!("Config: {} is not valid UTF-8", path);
+                Config::default()
+            }
+        },
+        Err(_) => Config::default(),
+    };
+
+    unsafe {
+        CONFIG = Some(config);
+    }
+}
+
+/// Get the loaded configuration
+pub fn config() -> &'static Config {
+    unsafe {
+        if CONFIG.is_none() {
+            CONFIG = Some(Config::default());
+        }
+        CONFIG.as_ref().unwrap()
+    }
+}
@@ -0,0 +1,184 @@
+use core::{char, slice};
+use std::proto::Protocol;
+use std::string::String;
+use std::vec::Vec;
+use uefi::Handle;
+use uefi::guid::Guid;
+
+pub const TYPE_MEDIA: u8 = 4;
+pub const TYPE_END: u8 = 0x7F;
+
+pub const SUBTYPE_END_ENTIRE: u8 = 0xFF;
+
+pub const SUBTYPE_HARD_DRIVE: u8 = 1;
+pub const SUBTYPE_FILE_PATH: u8 = 4;
+
+/// Signature type of a hard drive node with a GPT partition GUID
+pub const SIGNATURE_TYPE_GUID: u8 = 2;
+
+/// Header of the first node of a device path
+#[repr(C)]
+pub struct DevicePathData {
+    pub ty: u8,
+    pub sub_ty: u8,
+    pub len: [u8; 2],
+}
+
+pub struct DevicePath(pub &'static mut DevicePathData);
+
+impl Protocol<DevicePathData> for DevicePath {
+    fn guid() -> Guid {
+        Guid(0x09576e91, 0x6d3f, 0x11d2, [0x8e, 0x39, 0x00, 0xa0, 0xc9, 0x69, 0x72, 0x3b])
+    }
+
+    fn new(inner: &'static mut DevicePathData) -> Self {
+        Self(inner)
+    }
+}
+
+impl DevicePath {
+    pub fn iter(&self) -> DevicePathIter {
+        unsafe { DevicePathIter::new(&*self.0 as *const DevicePathData as *const u8) }
+    }
+}
+
+/// A single node of a device path
+pub struct DevicePathNode {
+    pub ty: u8,
+    pub sub_ty: u8,
+    pub data: &'static [u8],
+}
+
+/// Iterate over the nodes of a firmware device path, stopping at the end node
+pub struct DevicePathIter {
+    ptr: *const u8,
+}
+
+impl DevicePathIter {
+    /// Safety: `ptr` must point to a valid device path, or be null
+    pub unsafe fn new(ptr: *const u8) -> Self {
+        Self { ptr }
+    }
+}
+
+impl Iterator for DevicePathIter {
+    type Item = DevicePathNode;
+
+    fn next(&mut self) -> Option<DevicePathNode> {
+        if self.ptr.is_null() {
+            return None;
+        }
+
+        let (ty, sub_ty, len) = unsafe {
+            let header = slice::from_raw_parts(self.ptr, 4);
+            (header[0], header[1], header[2] as usize | (header[3] as usize) << 8)
+        };
+
+        if ty == TYPE_END || len < 4 {
+            self.ptr = core::ptr::null();
+            return None;
+        }
+
+        let data = unsafe { slice::from_raw_parts(self.ptr.add(4), len - 4) };
+        self.ptr = unsafe { self.ptr.add(len) };
+
+        Some(DevicePathNode { ty, sub_ty, data })
+    }
+}
+
+/// Decode the UCS-2 path name of a file path node
+pub fn file_path(node: &DevicePathNode) -> String {
+    let mut path = String::new();
+    for chunk in node.data.chunks_exact(2) {
+        let w = chunk[0] as u32 | (chunk[1] as u32) << 8;
+        if w == 0 {
+            break;
+        }
+        path.push(char::from_u32(w).unwrap_or('?'));
+    }
+    path
+}
+
+/// Build a device path for `path` on the device at `device`, as used by `LoadImage`
+pub fn with_file_path(device: &DevicePath, path: &str) -> Vec<u8> {
+    let mut data = Vec::new();
+    for node in device.iter() {
+        let len = node.data.len() + 4;
+        data.extend_from_slice(&[node.ty, node.sub_ty, len as u8, (len >> 8) as u8]);
+        data.extend_from_slice(node.data);
+    }
+
+    // The path name is NUL terminated UCS-2
+    let name: Vec<u16> = path.encode_utf16().chain(core::iter::once(0)).collect();
+    let len = name.len() * 2 + 4;
+    data.extend_from_slice(&[TYPE_MEDIA, SUBTYPE_FILE_PATH, len as u8, (len >> 8) as u8]);
+    for w in name {
+        data.extend_from_slice(&w.to_le_bytes());
+    }
+
+    data.extend_from_slice(&[TYPE_END, SUBTYPE_END_ENTIRE, 4, 0]);
+    data
+}
+
+/// Get the GPT partition GUID of a hard drive node
+pub fn partition_guid(node: &DevicePathNode) -> Option<[u8; 16]> {
+    // Partition number, start, and size come before the signature
+    if node.ty != TYPE_MEDIA || node.sub_ty != SUBTYPE_HARD_DRIVE || node.data.len() < 38 {
+        return None;
+    }
+    if node.data[37] != SIGNATURE_TYPE_GUID {
+        return None;
+    }
+
+    let mut guid = [0; 16];
+    guid.copy_from_slice(&node.data[20..36]);
+    Some(guid)
+}
+
+/// A key that orders devices by their path, so partitions sort by disk, then by partition
+/// number. Unlike the order the firmware returns handles in, this is the same every boot.
+pub fn sort_key(device: &DevicePath) -> Vec<Vec<u8>> {
+    device.iter().map(|node| {
+        let mut key = vec![node.ty, node.sub_ty];
+        if node.ty == TYPE_MEDIA && node.sub_ty == SUBTYPE_HARD_DRIVE && node.data.len() >= 4 {
+            // Big endian, so partition 10 sorts after partition 9
+            let number = u32::from_le_bytes([node.data[0], node.data[1], node.data[2], node.data[3]]);
+            key.extend_from_slice(&number.to_be_bytes());
+        } else {
+            key.extend_from_slice(node.data);
+        }
+        key
+    }).collect()
+}
+
+/// Check if `child` is a device below `parent`, such as a partition of a disk
+pub fn is_below(parent: &DevicePath, child: &DevicePath) -> bool {
+    let mut child_nodes = child.iter();
+    for node in parent.iter() {
+        match child_nodes.next() {
+            Some(child_node) if child_node.ty == node.ty && child_node.sub_ty == node.sub_ty && child_node.data == node.data => (),
+            _ => return false,
+        }
+    }
+    child_nodes.next().is_some()
+}
+
+/// Sort `handles` by their device paths with `sort_key`, putting handles without one last
+pub fn sort_handles(handles: &mut [Handle]) {
+    handles.sort_by_cached_key(|&handle| match DevicePath::handle_protocol(handle) {
+        Ok(device) => (false, sort_key(&device)),
+        Err(_) => (true, Vec::new()),
+    });
+}
+
+/// Format a GUID stored in the firmware's mixed endian layout
+pub fn guid_string(guid: &[u8; 16]) -> String {
+    format!(
+        "{:02x}{:02x}{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}",
+        guid[3], guid[2], guid[1], guid[0],
+        guid[5], guid[4],
+        guid[7], guid[6],
+        guid[8], guid[9],
+        guid[10], guid[11], guid[12], guid[13], guid[14], guid[15]
+    )
+}
@@ -2,10 +2,48 @@ use core::ops::{ControlFlow, Try};
 use redoxfs::{BLOCK_SIZE, Disk};
 use syscall::{EIO, Error, Result};
 use std::proto::Protocol;
+use std::vec::Vec;
 use uefi::guid::{Guid, BLOCK_IO_GUID};
 use uefi::block_io::BlockIo as UefiBlockIo;
+use util::disk::{block_lba, prefetch_len};
 
-pub struct DiskEfi(pub &'static mut UefiBlockIo);
+use crate::config::config;
+
+/// Data read ahead of what was asked for, starting at a filesystem block
+struct Prefetch {
+    block: u64,
+    data: Vec<u8>,
+}
+
+/// Totals for reads of the boot disk since the last `reset_read_stats`, passed on to the kernel
+#[derive(Clone, Copy, Debug)]
+pub struct ReadStats {
+    pub bytes: u64,
+    /// Firmware read calls, including failed ones
+    pub ops: u64,
+    /// Read-ahead windows of `read_prefetch` that failed and were read again directly.
+    /// Failed direct reads are not tried again, so this stays zero without `read_prefetch`.
+    pub retries: u64,
+}
+
+pub static mut READ_STATS: ReadStats = ReadStats { bytes: 0, ops: 0, retries: 0 };
+
+/// Start counting again, so the totals only cover reading the kernel and not the scan
+pub fn reset_read_stats() {
+    unsafe { READ_STATS = ReadStats { bytes: 0, ops: 0, retries: 0 }; }
+}
+
+/// Count a firmware read of `bytes`, which are only added if it succeeded
+fn count_read(bytes: usize, ok: bool) {
+    unsafe {
+        READ_STATS.ops += 1;
+        if ok {
+            READ_STATS.bytes += bytes as u64;
+        }
+    }
+}
+
+pub struct DiskEfi(pub &'static mut UefiBlockIo, Option<Prefetch>);
 
 impl Protocol<UefiBlockIo> for DiskEfi {
     fn guid() -> Guid {
@@ -13,17 +51,67 @@ impl Protocol<UefiBlockIo> for DiskEfi {
     }
 
     fn new(inner: &'static mut UefiBlockIo) -> Self {
-        Self(inner)
+        Self(inner, None)
     }
 }
 
-impl Disk for DiskEfi {
-    fn read_at(&mut self, block: u64, buffer: &mut [u8]) -> Result<usize> {
+impl DiskEfi {
+    /// Read whole blocks of the firmware's block size, starting at `lba`
+    pub fn read_blocks(&mut self, lba: u64, buffer: &mut [u8]) -> uefi::status::Result<()> {
+        let status = (self.0.ReadBlocks)(self.0, self.0.Media.MediaId, lba, buffer.len(), buffer.as_mut_ptr());
+        count_read(buffer.len(), status.branch().is_continue());
+        status?;
+        Ok(())
+    }
+
+    /// Read filesystem blocks like `read_at`, but read ahead so the following blocks
+    /// are served from memory. This saves a firmware call per metadata block on slow media.
+    /// Large reads go straight to the firmware, as BlockIo cannot overlap them with copying.
+    pub fn read_prefetch(&mut self, block: u64, buffer: &mut [u8]) -> Result<usize> {
+        if let Some(prefetch) = &self.1 {
+            let start = block.checked_sub(prefetch.block).map(|offset| offset * BLOCK_SIZE);
+            if let Some(start) = start.filter(|&start| start + buffer.len() as u64 <= prefetch.data.len() as u64) {
+                let start = start as usize;
+                buffer.copy_from_slice(&prefetch.data[start..start + buffer.len()]);
+                return Ok(buffer.len());
+            }
+        }
+
+        // Read a whole window, but not past the end of the disk
+        let block_size = self.0.Media.BlockSize as u64;
+        let disk_size = (self.0.Media.LastBlock + 1) * block_size;
+        let len = match prefetch_len(buffer.len(), block * BLOCK_SIZE, disk_size, block_size) {
+            Some(len) => len,
+            None => return self.read_direct(block, buffer),
+        };
+
+        let mut data = match self.1.take() {
+            Some(prefetch) => prefetch.data,
+            None => Vec::new(),
+        };
+        // Fragmented firmware heaps may not have room for the window, so read directly instead
+        if data.try_reserve_exact((len as usize).saturating_sub(data.len())).is_err() {
+            return self.read_direct(block, buffer);
+        }
+        data.resize(len as usize, 0);
+        if self.read_blocks(block_lba(block, BLOCK_SIZE, block_size), &mut data).branch().is_break() {
+            unsafe { READ_STATS.retries += 1; }
+            return self.read_direct(block, buffer);
+        }
+
+        buffer.copy_from_slice(&data[..buffer.len()]);
+        self.1 = Some(Prefetch { block, data });
+        Ok(buffer.len())
+    }
+
+    fn read_direct(&mut self, block: u64, buffer: &mut [u8]) -> Result<usize> {
         let block_size = self.0.Media.BlockSize as u64;
 
-        let lba = block * BLOCK_SIZE / block_size;
+        let lba = block_lba(block, BLOCK_SIZE, block_size);
 
-        match (self.0.ReadBlocks)(self.0, self.0.Media.MediaId, lba, buffer.len(), buffer.as_mut_ptr()).branch() {
+        let status = (self.0.ReadBlocks)(self.0, self.0.Media.MediaId, lba, buffer.len(), buffer.as_mut_ptr());
+        count_read(buffer.len(), status.branch().is_continue());
+        match status.branch() {
             ControlFlow::Continue(_) => Ok(buffer.len()),
             ControlFlow::Break(err) => {
                 println!("DiskEfi::read_at 0x{:X} failed: {:?}", block, err);
@@ -31,6 +119,16 @@ impl Disk for DiskEfi {
             }
         }
     }
+}
+
+impl Disk for DiskEfi {
+    fn read_at(&mut self, block: u64, buffer: &mut [u8]) -> Result<usize> {
+        if config().read_prefetch {
+            self.read_prefetch(block, buffer)
+        } else {
+            self.read_direct(block, buffer)
+        }
+    }
 
     fn write_at(&mut self, block: u64, _buffer: &[u8]) -> Result<usize> {
         println!("DiskEfi::write_at 0x{:X} not implemented", block);
@@ -1,4 +1,5 @@
 use core::cell::Cell;
+use core::cmp;
 use core::ops::Try;
 use core::ptr;
 use orbclient::{Color, Mode, Renderer};
@@ -6,6 +7,12 @@ use std::boxed::Box;
 use std::proto::Protocol;
 use uefi::graphics::{GraphicsOutput, GraphicsBltOp, GraphicsBltPixel};
 use uefi::guid::{Guid, GRAPHICS_OUTPUT_PROTOCOL_GUID};
+use uefi::status::Status;
+
+use crate::config::{config, Console, DisplayBackend};
+use crate::font;
+use crate::key::key;
+use crate::loaded_image;
 
 pub struct Output(pub &'static mut GraphicsOutput);
 
@@ -19,41 +26,299 @@ impl Protocol<GraphicsOutput> for Output {
     }
 }
 
+#[repr(C)]
+#[allow(dead_code)]
+pub enum UgaBltOp {
+    VideoFill,
+    VideoToBltBuffer,
+    BufferToVideo,
+    VideoToVideo,
+}
+
+/// The UGA Draw protocol, which predates GOP and is still found on old firmware
+#[repr(C)]
+#[allow(non_snake_case)]
+pub struct UgaDraw {
+    pub GetMode: extern "win64" fn(&mut UgaDraw, &mut u32, &mut u32, &mut u32, &mut u32) -> Status,
+    pub SetMode: extern "win64" fn(&mut UgaDraw, u32, u32, u32, u32) -> Status,
+    pub Blt: extern "win64" fn(&mut UgaDraw, *mut GraphicsBltPixel, UgaBltOp, usize, usize, usize, usize, usize, usize, usize) -> Status,
+}
+
+pub struct Uga(pub &'static mut UgaDraw);
+
+impl Protocol<UgaDraw> for Uga {
+    fn guid() -> Guid {
+        Guid(0x982c298b, 0xf4fa, 0x41cb, [0xb8, 0x38, 0x77, 0xaa, 0x68, 0x8f, 0xb8, 0x39])
+    }
+
+    fn new(inner: &'static mut UgaDraw) -> Self {
+        Uga(inner)
+    }
+}
+
+impl Uga {
+    /// Get the current resolution, if the protocol reports a usable mode
+    pub fn resolution(&mut self) -> Option<(u32, u32)> {
+        let mut w = 0;
+        let mut h = 0;
+        let mut depth = 0;
+        let mut refresh = 0;
+        if (self.0.GetMode)(self.0, &mut w, &mut h, &mut depth, &mut refresh).branch().is_continue() && w > 0 && h > 0 {
+            Some((w, h))
+        } else {
+            None
+        }
+    }
+}
+
+/// The way the bootloader draws to the screen
+pub enum Backend {
+    Gop(Output),
+    Uga(Uga),
+    /// Graphical backend that discards all drawing, for running headless
+    Null(u32, u32),
+    Text,
+}
+
+impl Backend {
+    /// Find the best available backend, trying GOP, then UGA, then falling back to text.
+    /// The `display` config key can force the text or null backend instead.
+    pub fn one() -> Self {
+        // Minimal builds and the serial console boot through text only
+        if cfg!(feature = "minimal") || config().console == Console::Serial {
+            return Backend::Text;
+        }
+
+        match config().display {
+            DisplayBackend::Auto => (),
+            DisplayBackend::Null => return Backend::Null(800, 600),
+            DisplayBackend::Text => return Backend::Text,
+        }
+
+        if let Ok(output) = Output::one() {
+            return Backend::Gop(output);
+        }
+
+        if let Ok(mut uga) = Uga::one() {
+            if uga.resolution().is_some() {
+                return Backend::Uga(uga);
+            }
+        }
+
+        Backend::Text
+    }
+
+    pub fn is_graphical(&self) -> bool {
+        match self {
+            Backend::Text => false,
+            _ => true,
+        }
+    }
+
+    pub fn resolution(&mut self) -> (u32, u32) {
+        match self {
+            Backend::Gop(output) => (
+                output.0.Mode.Info.HorizontalResolution,
+                output.0.Mode.Info.VerticalResolution,
+            ),
+            Backend::Uga(uga) => uga.resolution().unwrap_or((0, 0)),
+            Backend::Null(w, h) => (*w, *h),
+            Backend::Text => (0, 0),
+        }
+    }
+
+    /// Copy the whole screen into `buffer`, which is as wide as the screen
+    pub fn capture(&mut self, buffer: &mut [Color], w: u32, h: u32) -> bool {
+        let delta = w as usize * 4;
+        match self {
+            Backend::Gop(output) => (output.0.Blt)(
+                output.0,
+                buffer.as_mut_ptr() as *mut GraphicsBltPixel,
+                GraphicsBltOp::VideoToBltBuffer,
+                0,
+                0,
+                0,
+                0,
+                w as usize,
+                h as usize,
+                delta
+            ).branch().is_continue(),
+            Backend::Uga(uga) => (uga.0.Blt)(
+                uga.0,
+                buffer.as_mut_ptr() as *mut GraphicsBltPixel,
+                UgaBltOp::VideoToBltBuffer,
+                0,
+                0,
+                0,
+                0,
+                w as usize,
+                h as usize,
+                delta
+            ).branch().is_continue(),
+            Backend::Null(_w, _h) | Backend::Text => false,
+        }
+    }
+
+    /// Copy a rectangle of `buffer`, which is `stride` pixels wide, to the screen
+    pub fn blit(&mut self, buffer: &mut [Color], stride: u32, x: i32, y: i32, w: u32, h: u32) -> bool {
+        let delta = stride as usize * 4;
+        match self {
+            Backend::Gop(output) => (output.0.Blt)(
+                output.0,
+                buffer.as_mut_ptr() as *mut GraphicsBltPixel,
+                GraphicsBltOp::BufferToVideo,
+                x as usize,
+                y as usize,
+                x as usize,
+                y as usize,
+                w as usize,
+                h as usize,
+                delta
+            ).branch().is_continue(),
+            Backend::Uga(uga) => (uga.0.Blt)(
+                uga.0,
+                buffer.as_mut_ptr() as *mut GraphicsBltPixel,
+                UgaBltOp::BufferToVideo,
+                x as usize,
+                y as usize,
+                x as usize,
+                y as usize,
+                w as usize,
+                h as usize,
+                delta
+            ).branch().is_continue(),
+            Backend::Null(_w, _h) => true,
+            Backend::Text => false,
+        }
+    }
+
+    /// Move a rectangle of the screen to `(dst_x, dst_y)` with the firmware, without a buffer
+    pub fn copy_rect(&mut self, x: i32, y: i32, w: u32, h: u32, dst_x: i32, dst_y: i32) -> bool {
+        match self {
+            Backend::Gop(output) => (output.0.Blt)(
+                output.0,
+                ptr::null_mut(),
+                GraphicsBltOp::VideoToVideo,
+                x as usize,
+                y as usize,
+                dst_x as usize,
+                dst_y as usize,
+                w as usize,
+                h as usize,
+                0
+            ).branch().is_continue(),
+            Backend::Uga(uga) => (uga.0.Blt)(
+                uga.0,
+                ptr::null_mut(),
+                UgaBltOp::VideoToVideo,
+                x as usize,
+                y as usize,
+                dst_x as usize,
+                dst_y as usize,
+                w as usize,
+                h as usize,
+                0
+            ).branch().is_continue(),
+            Backend::Null(_w, _h) => true,
+            Backend::Text => false,
+        }
+    }
+}
+
+/// What the firmware left on the screen, with its resolution
+static mut FIRMWARE_SCREEN: Option<(u32, u32, Box<[Color]>)> = None;
+
 pub struct Display<'a> {
-    output: &'a mut Output,
+    backend: &'a mut Backend,
     w: u32,
     h: u32,
     data: Box<[Color]>,
     mode: Cell<Mode>,
+    firmware: bool,
 }
 
 impl<'a> Display<'a> {
-    pub fn new(output: &'a mut Output) -> Self {
-        let w = output.0.Mode.Info.HorizontalResolution;
-        let h = output.0.Mode.Info.VerticalResolution;
+    /// Create a display, starting from the firmware's screen with `preserve_firmware_logo`
+    pub fn new(backend: &'a mut Backend) -> Self {
+        let (w, h) = backend.resolution();
+        let mut data = vec![Color::rgb(0, 0, 0); w as usize * h as usize].into_boxed_slice();
+        let mut firmware = false;
+
+        if config().preserve_firmware_logo {
+            unsafe {
+                // Capture only once, before anything of ours is drawn over it
+                if FIRMWARE_SCREEN.is_none() && backend.capture(&mut data, w, h) {
+                    FIRMWARE_SCREEN = Some((w, h, data.clone()));
+                }
+                // The firmware's screen is lost after a mode change
+                if let Some((saved_w, saved_h, saved)) = &FIRMWARE_SCREEN {
+                    if *saved_w == w && *saved_h == h {
+                        data.copy_from_slice(saved);
+                        firmware = true;
+                    }
+                }
+            }
+        }
+
         Self {
-            output: output,
+            backend: backend,
             w: w,
             h: h,
-            data: vec![Color::rgb(0, 0, 0); w as usize * h as usize].into_boxed_slice(),
+            data: data,
             mode: Cell::new(Mode::Blend),
+            firmware: firmware,
         }
     }
 
+    /// Check if the display starts with what the firmware drew, which should not be cleared
+    pub fn has_firmware_content(&self) -> bool {
+        self.firmware
+    }
+
     pub fn blit(&mut self, x: i32, y: i32, w: u32, h: u32) -> bool {
-        let status = (self.output.0.Blt)(
-            self.output.0,
-            self.data.as_mut_ptr() as *mut GraphicsBltPixel,
-            GraphicsBltOp::BufferToVideo,
-            x as usize,
-            y as usize,
-            x as usize,
-            y as usize,
-            w as usize,
-            h as usize,
-            0
-        );
-        status.branch().is_continue()
+        self.backend.blit(&mut self.data, self.w, x, y, w, h)
+    }
+
+    /// Clip a rectangle and its destination to the display, returning the new source, size,
+    /// and destination, or `None` if nothing is left
+    fn clip_copy(&self, x: i32, y: i32, w: u32, h: u32, dst_x: i32, dst_y: i32) -> Option<(i32, i32, u32, u32, i32, i32)> {
+        // Cut whatever falls off the top or left of either rectangle from both
+        let left = cmp::max(0, -cmp::min(x, dst_x));
+        let top = cmp::max(0, -cmp::min(y, dst_y));
+        let (x, y, dst_x, dst_y) = (x + left, y + top, dst_x + left, dst_y + top);
+        let w = cmp::min(w as i64 - left as i64, self.w as i64 - cmp::max(x, dst_x) as i64);
+        let h = cmp::min(h as i64 - top as i64, self.h as i64 - cmp::max(y, dst_y) as i64);
+        if w <= 0 || h <= 0 {
+            None
+        } else {
+            Some((x, y, w as u32, h as u32, dst_x, dst_y))
+        }
+    }
+
+    /// Move a rectangle of the back buffer to `(dst_x, dst_y)`, the rectangles may overlap
+    pub fn copy_rect(&mut self, x: i32, y: i32, w: u32, h: u32, dst_x: i32, dst_y: i32) {
+        let (x, y, w, h, dst_x, dst_y) = match self.clip_copy(x, y, w, h, dst_x, dst_y) {
+            Some(clipped) => clipped,
+            None => return,
+        };
+
+        let stride = self.w as usize;
+        let row = |y: i32, x: i32| y as usize * stride + x as usize;
+        for i in 0..h as i32 {
+            // Copy rows in the order that does not overwrite rows still to be copied
+            let i = if dst_y > y { h as i32 - 1 - i } else { i };
+            let src = row(y + i, x);
+            self.data.copy_within(src..src + w as usize, row(dst_y + i, dst_x));
+        }
+    }
+
+    /// Move a rectangle on the screen like `copy_rect`, but without the back buffer. Returns
+    /// false if the backend can not, and the screen has to be redrawn from the back buffer.
+    pub fn copy_screen_rect(&mut self, x: i32, y: i32, w: u32, h: u32, dst_x: i32, dst_y: i32) -> bool {
+        match self.clip_copy(x, y, w, h, dst_x, dst_y) {
+            Some((x, y, w, h, dst_x, dst_y)) => self.backend.copy_rect(x, y, w, h, dst_x, dst_y),
+            None => true,
+        }
     }
 
     pub fn scroll(&mut self, rows: usize, color: Color) {
@@ -124,11 +389,39 @@ impl<'a> ScaledDisplay<'a> {
         self.scale
     }
 
+    pub fn has_firmware_content(&self) -> bool {
+        self.display.has_firmware_content()
+    }
+
     pub fn scroll(&mut self, rows: usize, color: Color) {
         let scale = self.scale as usize;
         self.display.scroll(rows * scale, color);
     }
 
+    pub fn copy_rect(&mut self, x: i32, y: i32, w: u32, h: u32, dst_x: i32, dst_y: i32) {
+        let scale = self.scale;
+        self.display.copy_rect(
+            x * scale as i32,
+            y * scale as i32,
+            w * scale,
+            h * scale,
+            dst_x * scale as i32,
+            dst_y * scale as i32
+        );
+    }
+
+    pub fn copy_screen_rect(&mut self, x: i32, y: i32, w: u32, h: u32, dst_x: i32, dst_y: i32) -> bool {
+        let scale = self.scale;
+        self.display.copy_screen_rect(
+            x * scale as i32,
+            y * scale as i32,
+            w * scale,
+            h * scale,
+            dst_x * scale as i32,
+            dst_y * scale as i32
+        )
+    }
+
     pub fn blit(&mut self, x: i32, y: i32, w: u32, h: u32) -> bool {
         let scale = self.scale;
         self.display.blit(
@@ -186,3 +479,45 @@ impl<'a> Renderer for ScaledDisplay<'a> {
         self.display.mode()
     }
 }
+
+/// Show a fatal error in a red banner, or on the console without graphics, then wait for a
+/// key before rebooting
+pub fn error_screen(msg: &str) {
+    // Started from the shell, the caller goes back to it instead
+    let prompt = if loaded_image::started_from_shell() {
+        "Press any key to return to the shell"
+    } else {
+        "Press any key to reboot"
+    };
+
+    let mut backend = Backend::one();
+    if backend.is_graphical() {
+        let mut display = Display::new(&mut backend);
+        let mut display = ScaledDisplay::new(&mut display);
+
+        let w = display.width() as i32;
+        let h = display.height() as i32;
+        let lines = [msg, "", prompt];
+        let banner_h = (lines.len() as i32 + 2) * 16;
+        let banner_y = (h - banner_h)/2;
+        display.rect(0, banner_y, w as u32, banner_h as u32, Color::rgb(0xc0, 0x20, 0x20));
+
+        for (i, line) in lines.iter().enumerate() {
+            // Cut long lines at the edge of the screen
+            let len = cmp::min(line.chars().count() as i32, w / 8);
+            let mut x = (w - len * 8)/2;
+            let y = banner_y + (i as i32 + 1) * 16;
+            for c in line.chars().take(len as usize) {
+                font::draw(&mut display, x, y, c, Color::rgb(0xff, 0xff, 0xff));
+                x += 8;
+            }
+        }
+
+        display.sync();
+    } else {
+        println!("Error: {}", msg);
+        println!("{}", prompt);
+    }
+
+    let _ = key(true);
+}
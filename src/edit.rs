@@ -0,0 +1,72 @@
+use core::cmp;
+use std::string::String;
+use std::vec::Vec;
+use uefi::status::Result;
+
+use crate::key::{key, Key};
+
+/// Edit a single line of text on the console, returning `None` if cancelled with escape
+pub fn edit_line(prompt: &str, initial: &str) -> Result<Option<String>> {
+    let uefi = std::system_table();
+
+    let mut cols = 80;
+    let mut rows = 0;
+    let _ = (uefi.ConsoleOut.QueryMode)(uefi.ConsoleOut, uefi.ConsoleOut.Mode.Mode as usize, &mut cols, &mut rows);
+
+    // Leave room for the prompt and one column for the cursor at the end of the line
+    let width = cmp::max(cols.saturating_sub(prompt.len() + 1), 1);
+
+    let mut line: Vec<char> = initial.chars().collect();
+    let mut cursor = line.len();
+    let mut scroll = 0;
+
+    let _ = (uefi.ConsoleOut.EnableCursor)(uefi.ConsoleOut, true);
+
+    let result = loop {
+        // Scroll horizontally so the cursor is always visible
+        if cursor < scroll {
+            scroll = cursor;
+        } else if cursor >= scroll + width {
+            scroll = cursor + 1 - width;
+        }
+
+        let visible: String = line.iter().skip(scroll).take(width).collect();
+        print!("\r{}{}", prompt, visible);
+        for _ in visible.chars().count()..width {
+            print!(" ");
+        }
+
+        let row = uefi.ConsoleOut.Mode.CursorRow as usize;
+        let _ = (uefi.ConsoleOut.SetCursorPosition)(uefi.ConsoleOut, prompt.len() + cursor - scroll, row);
+
+        match key(true)? {
+            Key::Left => if cursor > 0 {
+                cursor -= 1;
+            },
+            Key::Right => if cursor < line.len() {
+                cursor += 1;
+            },
+            Key::Home => cursor = 0,
+            Key::End => cursor = line.len(),
+            Key::Backspace => if cursor > 0 {
+                cursor -= 1;
+                line.remove(cursor);
+            },
+            Key::Delete => if cursor < line.len() {
+                line.remove(cursor);
+            },
+            Key::Enter => break Some(line.iter().collect()),
+            Key::Escape => break None,
+            Key::Character(c) => if !c.is_control() {
+                line.insert(cursor, c);
+                cursor += 1;
+            },
+            _ => (),
+        }
+    };
+
+    let _ = (uefi.ConsoleOut.EnableCursor)(uefi.ConsoleOut, false);
+    println!("");
+
+    Ok(result)
+}
@@ -0,0 +1,117 @@
+use std::vec::Vec;
+use uefi::status::{Error, Result};
+
+/// Loadable segment
+pub const PT_LOAD: u32 = 1;
+/// Segment of notes, see `find_note`
+pub const PT_NOTE: u32 = 4;
+
+/// Segment is executable
+pub const PF_X: u32 = 1;
+/// Segment is writable
+pub const PF_W: u32 = 2;
+/// Segment is readable
+pub const PF_R: u32 = 4;
+
+#[derive(Clone, Copy, Debug)]
+pub struct ProgramHeader {
+    pub p_type: u32,
+    pub p_flags: u32,
+    pub p_offset: u64,
+    pub p_vaddr: u64,
+    pub p_filesz: u64,
+    pub p_memsz: u64,
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16> {
+    let mut bytes = [0; 2];
+    bytes.copy_from_slice(data.get(offset..offset + 2).ok_or(Error::LoadError)?);
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(data.get(offset..offset + 4).ok_or(Error::LoadError)?);
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64> {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(data.get(offset..offset + 8).ok_or(Error::LoadError)?);
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Check if `data` starts with the ELF magic
+pub fn is_elf(data: &[u8]) -> bool {
+    data.get(..4) == Some(b"\x7FELF")
+}
+
+/// Read the entry point from the ELF header
+pub fn entry(data: &[u8]) -> Result<u64> {
+    read_u64(data, 0x18)
+}
+
+/// Read the program headers of a little endian 64-bit ELF file
+pub fn program_headers(data: &[u8]) -> Result<Vec<ProgramHeader>> {
+    if !is_elf(data) {
+        println!("ELF: invalid magic");
+        return Err(Error::LoadError);
+    }
+    // ELFCLASS64 and ELFDATA2LSB
+    if data.get(4) != Some(&2) || data.get(5) != Some(&1) {
+        println!("ELF: not a little endian 64-bit file");
+        return Err(Error::LoadError);
+    }
+
+    let phoff = read_u64(data, 0x20)? as usize;
+    let phentsize = read_u16(data, 0x36)? as usize;
+    let phnum = read_u16(data, 0x38)? as usize;
+    if phentsize < 0x38 {
+        println!("ELF: program header size {} is too small", phentsize);
+        return Err(Error::LoadError);
+    }
+
+    let mut headers = Vec::with_capacity(phnum);
+    for i in 0..phnum {
+        let offset = phentsize.checked_mul(i)
+            .and_then(|offset| offset.checked_add(phoff))
+            .ok_or(Error::LoadError)?;
+        headers.push(ProgramHeader {
+            p_type: read_u32(data, offset)?,
+            p_flags: read_u32(data, offset + 0x04)?,
+            p_offset: read_u64(data, offset + 0x08)?,
+            p_vaddr: read_u64(data, offset + 0x10)?,
+            p_filesz: read_u64(data, offset + 0x20)?,
+            p_memsz: read_u64(data, offset + 0x28)?,
+        });
+    }
+
+    Ok(headers)
+}
+
+/// Find the descriptor of the note with `name` and `ty` in the note segments
+pub fn find_note<'a>(data: &'a [u8], headers: &[ProgramHeader], name: &[u8], ty: u32) -> Option<&'a [u8]> {
+    for header in headers.iter().filter(|header| header.p_type == PT_NOTE) {
+        let start = header.p_offset as usize;
+        let notes = data.get(start..start.checked_add(header.p_filesz as usize)?)?;
+
+        let mut offset = 0;
+        while offset + 12 <= notes.len() {
+            let namesz = read_u32(notes, offset).ok()? as usize;
+            let descsz = read_u32(notes, offset + 4).ok()? as usize;
+            let note_ty = read_u32(notes, offset + 8).ok()?;
+            // The name and descriptor are each padded to 4 bytes
+            let name_start = offset + 12;
+            let desc_start = name_start.checked_add((namesz + 3) & !3)?;
+            let desc_end = desc_start.checked_add(descsz)?;
+
+            // The name includes its NUL terminator
+            let note_name = notes.get(name_start..name_start + namesz)?;
+            if note_ty == ty && note_name.strip_suffix(&[0]) == Some(name) {
+                return notes.get(desc_start..desc_end);
+            }
+            offset = desc_end.checked_add(3)? & !3;
+        }
+    }
+    None
+}
@@ -0,0 +1,52 @@
+use core::fmt;
+use uefi::status::Error;
+
+/// Reasons that booting can fail, with messages meant for the user
+#[derive(Debug)]
+pub enum BootError {
+    /// No partition with a supported filesystem was found
+    NoBootPartition,
+    /// The kernel was not found on the ESP or the boot partition
+    KernelNotFound,
+    /// The kernel is not a valid ELF file for this bootloader
+    InvalidElf,
+    /// The kernel does not match its stored checksum
+    ChecksumMismatch,
+    /// The kernel read back differently after copying, at this offset
+    CopyMismatch(usize),
+    /// The kernel was built for another version of the kernel arguments
+    AbiMismatch(u32),
+    /// The CPU lacks this feature, which the bootloader or kernel needs
+    UnsupportedCpu(&'static str),
+    /// Firmware ran out of memory while allocating
+    OutOfMemory,
+    /// Any other firmware error
+    Uefi(Error),
+}
+
+pub type BootResult<T> = core::result::Result<T, BootError>;
+
+impl From<Error> for BootError {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::OutOfResources => BootError::OutOfMemory,
+            err => BootError::Uefi(err),
+        }
+    }
+}
+
+impl fmt::Display for BootError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BootError::NoBootPartition => write!(f, "no bootable partition found"),
+            BootError::KernelNotFound => write!(f, "kernel not found on any partition"),
+            BootError::InvalidElf => write!(f, "kernel is not a valid ELF file"),
+            BootError::ChecksumMismatch => write!(f, "kernel does not match its checksum"),
+            BootError::CopyMismatch(offset) => write!(f, "kernel memory is not writable RAM, copy differs at offset {:X}", offset),
+            BootError::AbiMismatch(version) => write!(f, "kernel expects bootloader ABI v{}", version),
+            BootError::UnsupportedCpu(feature) => write!(f, "CPU does not support required feature {}", feature),
+            BootError::OutOfMemory => write!(f, "out of memory"),
+            BootError::Uefi(err) => write!(f, "firmware error {:?}", err),
+        }
+    }
+}
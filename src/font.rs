@@ -0,0 +1,48 @@
+use orbclient::{Color, Renderer};
+use std::vec::Vec;
+use util::font::{parse, FONT, GLYPHS, HEIGHT, WIDTH};
+
+use crate::fs::read_file;
+use crate::loaded_image::resolve;
+
+static mut LOADED: Option<Vec<[u8; 16]>> = None;
+
+/// Load `font.bin` from the bootloader directory, if present
+///
+/// The file holds 256 glyphs of 16 rows each, with the leftmost pixel in the
+/// high bit of every row, like `FONT`. Without it, `FONT` is used.
+pub fn load() {
+    let path = resolve("font.bin");
+    let data = match read_file(&path) {
+        Ok(data) => data,
+        Err(_) => return,
+    };
+
+    match parse(&data) {
+        Some(font) => unsafe {
+            LOADED = Some(font);
+        },
+        None => println!("Font: {} is {} bytes, expected {}", path, data.len(), GLYPHS * HEIGHT as usize),
+    }
+}
+
+/// The bitmap for `c` in the loaded font, or the built-in one, with a box for characters
+/// past Latin-1
+pub fn glyph(c: char) -> &'static [u8; 16] {
+    let font = match unsafe { LOADED.as_ref() } {
+        Some(font) => &font[..],
+        None => &FONT[..],
+    };
+    util::font::glyph(font, c)
+}
+
+/// Draw `c` with its top left corner at `x`, `y`
+pub fn draw<R: Renderer + ?Sized>(display: &mut R, x: i32, y: i32, c: char, color: Color) {
+    for (row, bits) in glyph(c).iter().enumerate() {
+        for col in 0..WIDTH {
+            if bits & (0x80 >> col) != 0 {
+                display.pixel(x + col, y + row as i32, color);
+            }
+        }
+    }
+}
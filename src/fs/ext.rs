@@ -0,0 +1,72 @@
+//! Read-only ext2, ext3, and ext4 support, enough to load a kernel from a Linux partition.
+//! The reader is in the util crate, this connects it to the firmware disks.
+
+use core::result;
+use ::redoxfs::Disk;
+use uefi::status::{Error, Result};
+use util::ext;
+
+use super::BootFs;
+
+pub use util::ext::MAGIC;
+
+/// A RedoxFS disk, which is read in the same 4 KiB blocks as `ext::Disk`
+struct ExtDisk<D: Disk>(D);
+
+impl<D: Disk> ext::Disk for ExtDisk<D> {
+    fn read_at(&mut self, block: u64, buffer: &mut [u8]) -> result::Result<(), ext::Error> {
+        self.0.read_at(block, buffer).map(|_| ()).map_err(|_| ext::Error::Device)
+    }
+}
+
+/// Convert an error of the ext reader, printing what went wrong if the status alone does
+/// not say
+fn error(err: ext::Error) -> Error {
+    match err {
+        ext::Error::Device => Error::DeviceError,
+        ext::Error::Corrupt => Error::VolumeCorrupted,
+        ext::Error::BlockSize(_) => {
+            warn!("ext: {}", err);
+            Error::Unsupported
+        },
+        ext::Error::DirSize(_) => {
+            warn!("ext: {}", err);
+            Error::VolumeCorrupted
+        },
+        ext::Error::NotDir(_) => {
+            warn!("ext: {}", err);
+            Error::NotFound
+        },
+        ext::Error::NotFound => Error::NotFound,
+    }
+}
+
+pub struct FileSystem<D: Disk>(ext::FileSystem<ExtDisk<D>>);
+
+impl<D: Disk> FileSystem<D> {
+    pub fn open(disk: D, block_opt: Option<u64>) -> Result<Self> {
+        ext::FileSystem::open(ExtDisk(disk), block_opt).map(FileSystem).map_err(error)
+    }
+}
+
+impl<D: Disk> BootFs for FileSystem<D> {
+    fn find_path(&mut self, path: &str) -> Result<u64> {
+        match self.0.find_path(path) {
+            Ok(inode) => Ok(inode.number as u64),
+            Err(ext::Error::NotFound) => {
+                warn!("ext: {} not found", path);
+                Err(Error::NotFound)
+            },
+            Err(err) => Err(error(err)),
+        }
+    }
+
+    fn file_size(&mut self, file: u64) -> Result<u64> {
+        Ok(self.0.inode(file as u32).map_err(error)?.size())
+    }
+
+    fn read(&mut self, file: u64, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        let inode = self.0.inode(file as u32).map_err(error)?;
+        self.0.read(&inode, offset, buffer).map_err(error)
+    }
+}
@@ -0,0 +1,183 @@
+use ::redoxfs::{BLOCK_SIZE, Disk, SIGNATURE};
+use std::boxed::Box;
+use std::fs::{find, Dir, File, FileSystem};
+use std::proto::Protocol;
+use std::string::String;
+use std::vec::Vec;
+use uefi::status::{Error, Result};
+use util::fs::name_matches;
+
+pub mod ext;
+pub mod redoxfs;
+
+/// Find the entry of `dir` matching `component`, returning its exact UCS-2 name
+fn find_entry(dir: &mut Dir, component: &str) -> Result<Vec<u16>> {
+    while let Some(info) = dir.read()? {
+        if name_matches(&info.FileName, component) {
+            let mut name: Vec<u16> = info.FileName.iter().cloned().take_while(|&w| w != 0).collect();
+            name.push(0);
+            return Ok(name);
+        }
+    }
+    Err(Error::NotFound)
+}
+
+/// Walk `path` from the root of every filesystem, matching each component without case
+fn find_case_insensitive(path: &str) -> Result<File> {
+    let components: Vec<&str> = path.split('\\').filter(|component| !component.is_empty()).collect();
+    let (last, dirs) = components.split_last().ok_or(Error::NotFound)?;
+
+    for mut fs in FileSystem::all() {
+        let mut dir = match fs.root() {
+            Ok(root) => root,
+            Err(_) => continue,
+        };
+
+        let mut found = true;
+        for component in dirs {
+            match find_entry(&mut dir, component).and_then(|name| dir.open_dir(&name)) {
+                Ok(next) => dir = next,
+                Err(_) => {
+                    found = false;
+                    break;
+                }
+            }
+        }
+
+        if found {
+            if let Ok(file) = find_entry(&mut dir, last).and_then(|name| dir.open(&name)) {
+                return Ok(file);
+            }
+        }
+    }
+
+    Err(Error::NotFound)
+}
+
+/// FileInfo attribute of directories
+const FILE_DIRECTORY: u64 = 0x10;
+
+/// List the names of the files, not directories, in the ESP directory at `path`
+pub fn list_dir(path: &str) -> Result<Vec<String>> {
+    let components: Vec<&str> = path.split('\\').filter(|component| !component.is_empty()).collect();
+
+    for mut fs in FileSystem::all() {
+        let mut dir = match fs.root() {
+            Ok(root) => root,
+            Err(_) => continue,
+        };
+
+        let mut found = true;
+        for component in components.iter() {
+            match find_entry(&mut dir, component).and_then(|name| dir.open_dir(&name)) {
+                Ok(next) => dir = next,
+                Err(_) => {
+                    found = false;
+                    break;
+                }
+            }
+        }
+        if !found {
+            continue;
+        }
+
+        let mut names = Vec::new();
+        while let Some(info) = dir.read()? {
+            if info.Attribute & FILE_DIRECTORY != 0 {
+                continue;
+            }
+            let len = info.FileName.iter().position(|&w| w == 0).unwrap_or(info.FileName.len());
+            names.push(String::from_utf16_lossy(&info.FileName[..len]));
+        }
+        return Ok(names);
+    }
+
+    Err(Error::NotFound)
+}
+
+/// Find a file on the ESP. The firmware resolves long names, but some FAT drivers
+/// match names by case, so fall back to searching each directory.
+pub fn find_file(path: &str) -> Result<File> {
+    match find(path) {
+        Ok((_i, file)) => Ok(file),
+        Err(_) => find_case_insensitive(path),
+    }
+}
+
+/// Read a whole file from the ESP into memory
+pub fn read_file(path: &str) -> Result<Vec<u8>> {
+    let mut file = find_file(path)?;
+    let info = file.info()?;
+    let len = info.FileSize as usize;
+
+    let mut data = vec![0; len];
+    let mut i = 0;
+    while i < len {
+        let count = file.read(&mut data[i..])?;
+        if count == 0 {
+            break;
+        }
+        i += count;
+    }
+    data.truncate(i);
+
+    Ok(data)
+}
+
+/// A filesystem found on the boot partition
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FsKind {
+    RedoxFs,
+    Ext,
+}
+
+/// Identify the filesystem starting at `block` by its superblock
+pub fn detect<D: Disk>(disk: &mut D, block: u64) -> Result<FsKind> {
+    let mut data = [0; BLOCK_SIZE as usize];
+    disk.read_at(block, &mut data).map_err(|_| Error::DeviceError)?;
+
+    if &data[..SIGNATURE.len()] == SIGNATURE {
+        Ok(FsKind::RedoxFs)
+    } else if u16::from_le_bytes([data[0x438], data[0x439]]) == ext::MAGIC {
+        Ok(FsKind::Ext)
+    } else {
+        Err(Error::Unsupported)
+    }
+}
+
+/// A filesystem the kernel can be loaded from. Files are identified by a number
+/// that only has a meaning to the filesystem, such as an inode number.
+pub trait BootFs {
+    /// Walk a `/` separated path from the root directory
+    fn find_path(&mut self, path: &str) -> Result<u64>;
+
+    fn file_size(&mut self, file: u64) -> Result<u64>;
+
+    /// Read from `file` at `offset`, returning the number of bytes read
+    fn read(&mut self, file: u64, offset: u64, buffer: &mut [u8]) -> Result<usize>;
+
+    /// Variables describing the filesystem for the kernel environment
+    fn env(&self) -> String {
+        String::new()
+    }
+}
+
+/// Open the filesystem starting at `block_opt`, whichever kind it is
+pub fn open<D: Disk + 'static>(mut disk: D, block_opt: Option<u64>) -> Result<Box<dyn BootFs>> {
+    let block = block_opt.unwrap_or(0);
+    match detect(&mut disk, block) {
+        Ok(FsKind::RedoxFs) => {
+            let mut fs = ::redoxfs::FileSystem::open(disk, block_opt).map_err(|_| Error::DeviceError)?;
+            self::redoxfs::verify(&mut fs)?;
+            Ok(Box::new(fs))
+        },
+        Ok(FsKind::Ext) => {
+            info!("Found an ext filesystem");
+            Ok(Box::new(ext::FileSystem::open(disk, block_opt)?))
+        },
+        Err(err) => {
+            error!("No supported filesystem at block {:X}", block);
+            Err(err)
+        },
+    }
+}
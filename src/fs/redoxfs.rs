@@ -0,0 +1,95 @@
+use ::redoxfs::{BLOCK_SIZE, Disk, FileSystem, Node, SIGNATURE, VERSION};
+use std::string::String;
+use uefi::status::{Error, Result};
+
+use super::BootFs;
+
+/// Walk a `/` separated path from the directory node at `root`, returning the final node
+pub fn find_path<D: Disk>(fs: &mut FileSystem<D>, path: &str, root: u64) -> Result<(u64, Node)> {
+    let mut found: Option<(u64, Node)> = None;
+
+    for component in path.split('/').filter(|component| !component.is_empty()) {
+        let parent = match found {
+            Some((block, ref node)) => if node.is_dir() {
+                block
+            } else {
+                println!("RedoxFS: {} is not a directory", node.name().unwrap_or("?"));
+                return Err(Error::NotFound);
+            },
+            None => root,
+        };
+
+        match fs.find_node(component, parent) {
+            Ok(node) => found = Some(node),
+            Err(_) => {
+                println!("RedoxFS: {} not found in {}", component, path);
+                return Err(Error::NotFound);
+            }
+        }
+    }
+
+    found.ok_or(Error::NotFound)
+}
+
+/// Check that the header of an opened filesystem is sane and its root node is a directory,
+/// so a corrupt filesystem is reported before the kernel is looked up
+pub fn verify<D: Disk>(fs: &mut FileSystem<D>) -> Result<()> {
+    // Copy the fields out, the header is packed
+    let header = &fs.header.1;
+    let (signature, version, root, free) = (header.signature, header.version, header.root, header.free);
+    let blocks = header.size / BLOCK_SIZE;
+
+    let problem = if &signature != SIGNATURE {
+        Some("bad signature")
+    } else if version != VERSION {
+        Some("unexpected version")
+    } else if blocks == 0 {
+        Some("empty filesystem")
+    } else if root == 0 || root >= blocks {
+        Some("root node outside of the filesystem")
+    } else if free >= blocks {
+        Some("free list outside of the filesystem")
+    } else {
+        match fs.node(root) {
+            Ok((_, node)) if node.is_dir() => None,
+            Ok(_) => Some("root node is not a directory"),
+            Err(_) => Some("root node can not be read"),
+        }
+    };
+
+    match problem {
+        Some(problem) => {
+            error!("RedoxFS header corrupt at block {:X}: {}", fs.block, problem);
+            Err(Error::VolumeCorrupted)
+        },
+        None => Ok(()),
+    }
+}
+
+impl<D: Disk> BootFs for FileSystem<D> {
+    fn find_path(&mut self, path: &str) -> Result<u64> {
+        let root = self.header.1.root;
+        Ok(find_path(self, path, root)?.0)
+    }
+
+    fn file_size(&mut self, file: u64) -> Result<u64> {
+        self.node_len(file).map_err(|_| Error::DeviceError)
+    }
+
+    fn read(&mut self, file: u64, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        self.read_node(file, offset, buffer, 0, 0).map_err(|_| Error::DeviceError)
+    }
+
+    fn env(&self) -> String {
+        let mut env = format!("REDOXFS_BLOCK={:016x}\n", self.block);
+        env.push_str("REDOXFS_UUID=");
+        for i in 0..self.header.1.uuid.len() {
+            if i == 4 || i == 6 || i == 8 || i == 10 {
+                env.push('-');
+            }
+
+            env.push_str(&format!("{:>02x}", self.header.1.uuid[i]));
+        }
+        env
+    }
+}
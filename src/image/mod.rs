@@ -4,8 +4,10 @@ use std::boxed::Box;
 use std::string::{String, ToString};
 
 use orbclient::{Color, Mode, Renderer};
+use util::image::visible_rows;
 
 pub mod bmp;
+pub mod png;
 
 pub struct ImageRoi<'a> {
     x: u32,
@@ -89,9 +91,11 @@ impl Image {
         self.data
     }
 
-    /// Draw the image on a window
+    /// Draw the image on a window, cropping the parts outside of it
     pub fn draw<R: Renderer>(&self, renderer: &mut R, x: i32, y: i32) {
-        renderer.image_legacy(x, y, self.w, self.h, &self.data);
+        for row in visible_rows(self.w, self.h, x, y, renderer.width(), renderer.height()) {
+            renderer.image_legacy(row.x, row.y, row.len as u32, 1, &self.data[row.offset..row.offset + row.len]);
+        }
     }
 }
 
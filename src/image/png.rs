@@ -0,0 +1,137 @@
+use std::string::{String, ToString};
+use std::vec::Vec;
+use orbclient::Color;
+
+use super::Image;
+
+/// Largest width or height accepted, so a bad file can not ask for more than 64 MiB of pixels
+const MAX_SIZE: u32 = 4096;
+
+fn be32(data: &[u8], i: usize) -> u32 {
+    (data[i] as u32) << 24 | (data[i + 1] as u32) << 16 | (data[i + 2] as u32) << 8 | data[i + 3] as u32
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Parse a non-interlaced PNG with 8 bits per channel
+pub fn parse(file_data: &[u8]) -> Result<Image, String> {
+    if file_data.len() < 8 || &file_data[..8] != b"\x89PNG\r\n\x1a\n" {
+        return Err("PNG: invalid signature".to_string());
+    }
+
+    let mut width = 0;
+    let mut height = 0;
+    let mut color_type = 0;
+    let mut palette: Vec<Color> = Vec::new();
+    let mut idat = Vec::new();
+
+    let mut i = 8;
+    while i + 8 <= file_data.len() {
+        let len = be32(file_data, i) as usize;
+        let kind = &file_data[i + 4..i + 8];
+        let start = i + 8;
+        let end = start.checked_add(len).filter(|&end| end <= file_data.len())
+            .ok_or_else(|| "PNG: truncated chunk".to_string())?;
+        let chunk = &file_data[start..end];
+
+        match kind {
+            b"IHDR" => {
+                if chunk.len() < 13 {
+                    return Err("PNG: invalid header".to_string());
+                }
+                width = be32(chunk, 0);
+                height = be32(chunk, 4);
+                let depth = chunk[8];
+                color_type = chunk[9];
+                let interlace = chunk[12];
+                if depth != 8 || interlace != 0 {
+                    return Err(format!("PNG: unsupported depth {} or interlace {}", depth, interlace));
+                }
+                if width == 0 || height == 0 || width > MAX_SIZE || height > MAX_SIZE {
+                    return Err(format!("PNG: unsupported size {}x{}", width, height));
+                }
+            },
+            b"PLTE" => {
+                palette = chunk.chunks_exact(3).map(|rgb| Color::rgb(rgb[0], rgb[1], rgb[2])).collect();
+            },
+            b"tRNS" => if color_type == 3 {
+                for (color, alpha) in palette.iter_mut().zip(chunk.iter()) {
+                    *color = Color::rgba(color.r(), color.g(), color.b(), *alpha);
+                }
+            },
+            b"IDAT" => idat.extend_from_slice(chunk),
+            b"IEND" => break,
+            _ => (),
+        }
+
+        // Skip the CRC
+        i = end + 4;
+    }
+
+    let channels = match color_type {
+        0 | 3 => 1,
+        4 => 2,
+        2 => 3,
+        6 => 4,
+        _ => return Err(format!("PNG: unsupported color type {}", color_type)),
+    };
+    if width == 0 {
+        return Err("PNG: missing header".to_string());
+    }
+
+    let raw = miniz_oxide::inflate::decompress_to_vec_zlib(&idat)
+        .map_err(|err| format!("PNG: failed to decompress: {:?}", err))?;
+
+    let stride = width as usize * channels;
+    if raw.len() < (stride + 1) * height as usize {
+        return Err("PNG: not enough image data".to_string());
+    }
+
+    // Undo the per-row filters in place
+    let mut pixels = vec![0u8; stride * height as usize];
+    for y in 0..height as usize {
+        let filter = raw[y * (stride + 1)];
+        let src = &raw[y * (stride + 1) + 1..(y + 1) * (stride + 1)];
+        let (prev, row) = pixels.split_at_mut(y * stride);
+        let prev = if y > 0 { &prev[(y - 1) * stride..] } else { &[][..] };
+        let row = &mut row[..stride];
+        for x in 0..stride {
+            let a = if x >= channels { row[x - channels] } else { 0 };
+            let b = prev.get(x).copied().unwrap_or(0);
+            let c = if x >= channels { prev.get(x - channels).copied().unwrap_or(0) } else { 0 };
+            row[x] = src[x].wrapping_add(match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return Err(format!("PNG: invalid filter {}", filter)),
+            });
+        }
+    }
+
+    let mut data = Vec::with_capacity(width as usize * height as usize);
+    for pixel in pixels.chunks_exact(channels) {
+        data.push(match color_type {
+            0 => Color::rgb(pixel[0], pixel[0], pixel[0]),
+            2 => Color::rgb(pixel[0], pixel[1], pixel[2]),
+            3 => *palette.get(pixel[0] as usize).ok_or_else(|| "PNG: invalid palette index".to_string())?,
+            4 => Color::rgba(pixel[0], pixel[0], pixel[0], pixel[1]),
+            _ => Color::rgba(pixel[0], pixel[1], pixel[2], pixel[3]),
+        });
+    }
+
+    Image::from_data(width, height, data.into_boxed_slice())
+}
@@ -0,0 +1,19 @@
+use util::integrity::parse_crc32;
+
+use crate::fs::read_file;
+use crate::loaded_image::resolve;
+
+pub use util::integrity::crc32;
+
+/// Read the expected kernel CRC-32 from `kernel.crc32`, if it exists
+pub fn expected_kernel_crc32() -> Option<u32> {
+    let path = resolve("kernel.crc32");
+    let data = read_file(&path).ok()?;
+    match parse_crc32(&data) {
+        Some(crc) => Some(crc),
+        None => {
+            println!("{} does not contain 8 hexadecimal digits", path);
+            None
+        }
+    }
+}
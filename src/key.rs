@@ -1,7 +1,56 @@
 use core::char;
-use uefi::status::Result;
+use std::proto::Protocol;
+use uefi::Event;
+use uefi::guid::Guid;
+use uefi::status::{Result, Status};
 use uefi::text::TextInputKey;
 
+use crate::config::config;
+use crate::layout;
+use crate::watchdog;
+
+/// The shift state reported by the firmware is valid
+const SHIFT_STATE_VALID: u32 = 0x8000_0000;
+/// The right alt key, AltGr on most non-US layouts
+const RIGHT_ALT_PRESSED: u32 = 0x10;
+
+#[repr(C)]
+#[allow(non_snake_case)]
+pub struct KeyState {
+    pub KeyShiftState: u32,
+    pub KeyToggleState: u8,
+}
+
+#[repr(C)]
+#[allow(non_snake_case)]
+pub struct KeyData {
+    pub Key: TextInputKey,
+    pub KeyState: KeyState,
+}
+
+#[repr(C)]
+#[allow(non_snake_case)]
+pub struct SimpleTextInputEx {
+    pub Reset: extern "win64" fn(&SimpleTextInputEx, bool) -> Status,
+    pub ReadKeyStrokeEx: extern "win64" fn(&SimpleTextInputEx, &mut KeyData) -> Status,
+    pub WaitForKeyEx: Event,
+    pub SetState: extern "win64" fn(&SimpleTextInputEx, &u8) -> Status,
+    pub RegisterKeyNotify: usize,
+    pub UnregisterKeyNotify: usize,
+}
+
+pub struct InputEx(pub &'static mut SimpleTextInputEx);
+
+impl Protocol<SimpleTextInputEx> for InputEx {
+    fn guid() -> Guid {
+        Guid(0xdd9e7534, 0x7762, 0x4698, [0x8c, 0x14, 0xf5, 0x85, 0x17, 0xa6, 0x25, 0xaa])
+    }
+
+    fn new(inner: &'static mut SimpleTextInputEx) -> Self {
+        Self(inner)
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub enum Key {
     Backspace,
@@ -75,8 +124,12 @@ pub fn raw_key(wait: bool) -> Result<TextInputKey> {
     let uefi = std::system_table();
 
     if wait {
+        // Waiting on the user is not a stall
+        watchdog::suspend();
         let mut index = 0;
-        (uefi.BootServices.WaitForEvent)(1, &uefi.ConsoleIn.WaitForKey, &mut index)?;
+        let status = (uefi.BootServices.WaitForEvent)(1, &uefi.ConsoleIn.WaitForKey, &mut index);
+        watchdog::resume();
+        status?;
     }
 
     let mut key = TextInputKey {
@@ -89,7 +142,48 @@ pub fn raw_key(wait: bool) -> Result<TextInputKey> {
     Ok(key)
 }
 
+/// Read a key with its shift state through `SimpleTextInputEx`
+fn raw_key_ex(input: &InputEx, wait: bool) -> Result<KeyData> {
+    let uefi = std::system_table();
+
+    if wait {
+        // Waiting on the user is not a stall
+        watchdog::suspend();
+        let mut index = 0;
+        let status = (uefi.BootServices.WaitForEvent)(1, &input.0.WaitForKeyEx, &mut index);
+        watchdog::resume();
+        status?;
+    }
+
+    let mut key_data = KeyData {
+        Key: TextInputKey {
+            ScanCode: 0,
+            UnicodeChar: 0
+        },
+        KeyState: KeyState {
+            KeyShiftState: 0,
+            KeyToggleState: 0,
+        },
+    };
+
+    (input.0.ReadKeyStrokeEx)(input.0, &mut key_data)?;
+
+    Ok(key_data)
+}
+
 pub fn key(wait: bool) -> Result<Key> {
-    let raw_key = raw_key(wait)?;
-    Ok(Key::from(raw_key))
+    // Only the extended protocol reports modifiers, which are needed for AltGr
+    let (raw_key, altgr) = match InputEx::handle_protocol(std::system_table().ConsoleInHandle) {
+        Ok(input) => {
+            let key_data = raw_key_ex(&input, wait)?;
+            let shift = key_data.KeyState.KeyShiftState;
+            (key_data.Key, shift & SHIFT_STATE_VALID != 0 && shift & RIGHT_ALT_PRESSED != 0)
+        },
+        Err(_) => (raw_key(wait)?, false),
+    };
+
+    Ok(match Key::from(raw_key) {
+        Key::Character(c) => Key::Character(layout::map(config().keyboard_layout, c, altgr)),
+        key => key,
+    })
 }
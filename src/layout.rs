@@ -0,0 +1,132 @@
+//! Translate characters typed on a non-US keyboard, which firmware reports as if it were US
+
+use crate::config::KeyboardLayout;
+
+/// Map the character a US keyboard would produce to the one printed on the key
+pub fn map(layout: KeyboardLayout, c: char, altgr: bool) -> char {
+    match layout {
+        KeyboardLayout::Us => c,
+        KeyboardLayout::De => if altgr { de_altgr(c) } else { de(c) },
+        KeyboardLayout::Fr => if altgr { fr_altgr(c) } else { fr(c) },
+    }
+}
+
+fn de(c: char) -> char {
+    match c {
+        'y' => 'z',
+        'z' => 'y',
+        'Y' => 'Z',
+        'Z' => 'Y',
+        '-' => 'ß',
+        '_' => '?',
+        '[' => 'ü',
+        '{' => 'Ü',
+        ']' => '+',
+        '}' => '*',
+        ';' => 'ö',
+        ':' => 'Ö',
+        '\'' => 'ä',
+        '"' => 'Ä',
+        '\\' => '#',
+        '|' => '\'',
+        '/' => '-',
+        '?' => '_',
+        '<' => ';',
+        '>' => ':',
+        '`' => '^',
+        '~' => '°',
+        '@' => '"',
+        '#' => '§',
+        '^' => '&',
+        '&' => '/',
+        '*' => '(',
+        '(' => ')',
+        ')' => '=',
+        _ => c,
+    }
+}
+
+fn de_altgr(c: char) -> char {
+    match c {
+        'q' => '@',
+        'e' => '€',
+        '7' => '{',
+        '8' => '[',
+        '9' => ']',
+        '0' => '}',
+        '-' => '\\',
+        ']' => '~',
+        _ => de(c),
+    }
+}
+
+fn fr(c: char) -> char {
+    match c {
+        'a' => 'q',
+        'q' => 'a',
+        'z' => 'w',
+        'w' => 'z',
+        'A' => 'Q',
+        'Q' => 'A',
+        'Z' => 'W',
+        'W' => 'Z',
+        ';' => 'm',
+        ':' => 'M',
+        'm' => ',',
+        'M' => '?',
+        ',' => ';',
+        '<' => '.',
+        '.' => ':',
+        '>' => '/',
+        '/' => '!',
+        '?' => '§',
+        '1' => '&',
+        '2' => 'é',
+        '3' => '"',
+        '4' => '\'',
+        '5' => '(',
+        '6' => '-',
+        '7' => 'è',
+        '8' => '_',
+        '9' => 'ç',
+        '0' => 'à',
+        '!' => '1',
+        '@' => '2',
+        '#' => '3',
+        '$' => '4',
+        '%' => '5',
+        '^' => '6',
+        '&' => '7',
+        '*' => '8',
+        '(' => '9',
+        ')' => '0',
+        '-' => ')',
+        '_' => '°',
+        ']' => '$',
+        '}' => '£',
+        '\'' => 'ù',
+        '"' => '%',
+        '\\' => '*',
+        '|' => 'µ',
+        '`' => '²',
+        _ => c,
+    }
+}
+
+fn fr_altgr(c: char) -> char {
+    match c {
+        'e' => '€',
+        '2' => '~',
+        '3' => '#',
+        '4' => '{',
+        '5' => '[',
+        '6' => '|',
+        '7' => '`',
+        '8' => '\\',
+        '9' => '^',
+        '0' => '@',
+        '-' => ']',
+        '=' => '}',
+        _ => fr(c),
+    }
+}
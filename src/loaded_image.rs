@@ -0,0 +1,119 @@
+use std::proto::Protocol;
+use std::string::{String, ToString};
+use uefi::Handle;
+use uefi::guid::Guid;
+use uefi::memory::MemoryType;
+use uefi::status::Status;
+
+use crate::device_path::{self, DevicePath, DevicePathIter, SUBTYPE_FILE_PATH, TYPE_MEDIA};
+use crate::fs::find_file;
+use crate::proto::has_protocol;
+
+/// EFI_SHELL_PARAMETERS_PROTOCOL, installed on images started from the UEFI shell
+const SHELL_PARAMETERS_GUID: Guid = Guid(0x752f3136, 0x4e16, 0x4fdc, [0xa2, 0x2a, 0xe5, 0xf4, 0x68, 0x12, 0xf4, 0xca]);
+/// EFI_SHELL_PROTOCOL
+const SHELL_GUID: Guid = Guid(0x6302d008, 0x7f9b, 0x4f30, [0x87, 0xac, 0x60, 0xc9, 0xfe, 0xf5, 0xda, 0x4e]);
+
+static mut BASE_DIR: Option<String> = None;
+
+#[repr(C)]
+#[allow(non_snake_case)]
+pub struct LoadedImageData {
+    pub Revision: u32,
+    pub ParentHandle: Handle,
+    pub SystemTable: usize,
+    pub DeviceHandle: Handle,
+    pub FilePath: *const u8,
+    pub Reserved: usize,
+    pub LoadOptionsSize: u32,
+    pub LoadOptions: *const u8,
+    pub ImageBase: usize,
+    pub ImageSize: u64,
+    pub ImageCodeType: MemoryType,
+    pub ImageDataType: MemoryType,
+    pub Unload: extern "win64" fn(Handle) -> Status,
+}
+
+pub struct LoadedImage(pub &'static mut LoadedImageData);
+
+impl Protocol<LoadedImageData> for LoadedImage {
+    fn guid() -> Guid {
+        Guid(0x5b1b31a1, 0x9562, 0x11d2, [0x8e, 0x3f, 0x00, 0xa0, 0xc9, 0x69, 0x72, 0x3b])
+    }
+
+    fn new(inner: &'static mut LoadedImageData) -> Self {
+        Self(inner)
+    }
+}
+
+impl LoadedImage {
+    /// The loaded image protocol of the bootloader itself
+    pub fn current() -> Option<Self> {
+        Self::handle_protocol(std::handle()).ok()
+    }
+
+    /// The path of the image file on its device, such as `\EFI\BOOT\BOOTX64.EFI`
+    pub fn file_path(&self) -> Option<String> {
+        let mut path = String::new();
+        for node in unsafe { DevicePathIter::new(self.0.FilePath) } {
+            if node.ty == TYPE_MEDIA && node.sub_ty == SUBTYPE_FILE_PATH {
+                let part = device_path::file_path(&node);
+                if !path.ends_with('\\') && !part.starts_with('\\') {
+                    path.push('\\');
+                }
+                path.push_str(&part);
+            }
+        }
+
+        if path.is_empty() {
+            None
+        } else {
+            Some(path)
+        }
+    }
+
+    /// The GPT partition GUID of the device the image was loaded from
+    pub fn partition_guid(&self) -> Option<[u8; 16]> {
+        let device_path = DevicePath::handle_protocol(self.0.DeviceHandle).ok()?;
+        device_path.iter().find_map(|node| device_path::partition_guid(&node))
+    }
+}
+
+/// Check if the bootloader was started from the UEFI shell, instead of as a boot option
+pub fn started_from_shell() -> bool {
+    if has_protocol(std::handle(), &SHELL_PARAMETERS_GUID) {
+        return true;
+    }
+    // Otherwise check if the image that started us is the shell
+    LoadedImage::current().map_or(false, |image| has_protocol(image.0.ParentHandle, &SHELL_GUID))
+}
+
+/// The directory the bootloader was loaded from, falling back to the compiled in `BASEDIR`
+pub fn base_dir() -> &'static str {
+    unsafe {
+        if BASE_DIR.is_none() {
+            let dir = LoadedImage::current()
+                .and_then(|image| image.file_path())
+                .and_then(|path| path.rfind('\\').map(|i| path[..i].to_string()))
+                .filter(|dir| !dir.is_empty())
+                .unwrap_or_else(|| format!("\\{}", env!("BASEDIR")));
+            BASE_DIR = Some(dir);
+        }
+        BASE_DIR.as_ref().unwrap()
+    }
+}
+
+/// Find a file relative to the bootloader directory, then relative to the compiled in `BASEDIR`
+pub fn resolve(name: &str) -> String {
+    let path = format!("{}\\{}", base_dir(), name);
+    if find_file(&path).is_ok() {
+        return path;
+    }
+
+    let fallback = format!("\\{}\\{}", env!("BASEDIR"), name);
+    if find_file(&fallback).is_ok() {
+        return fallback;
+    }
+
+    path
+}
@@ -0,0 +1,62 @@
+//! Leveled messages, written to the screen and to the serial port
+
+use core::fmt;
+
+use crate::config::{config, Console, LogLevel};
+
+static mut SERIAL_READY: bool = false;
+
+#[cfg(target_arch = "x86_64")]
+pub fn serial_write(string: &str) {
+    unsafe {
+        if !SERIAL_READY {
+            crate::arch::serial::init();
+            SERIAL_READY = true;
+        }
+        crate::arch::serial::write_str(string);
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn serial_write(_string: &str) {}
+
+/// Check if messages of `level` are shown with the configured `log_level`
+pub fn enabled(level: LogLevel) -> bool {
+    level <= config().log_level
+}
+
+/// Write a message with a level tag, if the level is enabled. Use the macros instead.
+pub fn log(level: LogLevel, args: fmt::Arguments) {
+    if !enabled(level) {
+        return;
+    }
+
+    let tag = match level {
+        LogLevel::Error => "ERROR",
+        LogLevel::Warn => "WARN",
+        LogLevel::Info => "INFO",
+        LogLevel::Debug => "DEBUG",
+    };
+    let line = format!("[{}] {}\n", tag, args);
+    print!("{}", line);
+    // With the serial console, printing already wrote to the serial port
+    if config().console == Console::Both {
+        serial_write(&line);
+    }
+}
+
+macro_rules! error {
+    ($($arg:tt)*) => ($crate::log::log($crate::config::LogLevel::Error, format_args!($($arg)*)));
+}
+
+macro_rules! warn {
+    ($($arg:tt)*) => ($crate::log::log($crate::config::LogLevel::Warn, format_args!($($arg)*)));
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => ($crate::log::log($crate::config::LogLevel::Info, format_args!($($arg)*)));
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => ($crate::log::log($crate::config::LogLevel::Debug, format_args!($($arg)*)));
+}
@@ -5,9 +5,12 @@
 #![feature(core_intrinsics)]
 #![feature(control_flow_enum)]
 #![feature(prelude_import)]
+#![feature(try_reserve)]
 #![feature(try_trait_v2)]
 #![feature(untagged_unions)]
 
+// This also provides the panic handler, which prints only to the firmware console. A
+// second one here would not link, so panics do not reach the serial port.
 #[macro_use]
 extern crate uefi_std as std;
 
@@ -16,13 +19,45 @@ use core::ptr;
 use uefi::reset::ResetType;
 use uefi::status::{Result, Status};
 
+#[macro_use]
+mod log;
+
 mod arch;
+mod chainload;
+mod compress;
+mod config;
+mod device_path;
 mod disk;
+// Only the backend and error screen are used without the graphical UI
+#[cfg_attr(feature = "minimal", allow(dead_code))]
 mod display;
+mod edit;
+mod elf;
+mod error;
+#[cfg_attr(feature = "minimal", allow(dead_code))]
+mod font;
+mod fs;
+#[cfg(not(feature = "minimal"))]
 pub mod image;
+mod integrity;
 mod key;
+mod layout;
+mod loaded_image;
+#[cfg(feature = "net")]
+mod net;
 pub mod null;
+mod proto;
+mod rng;
+mod secret;
+#[cfg(target_arch = "x86_64")]
+pub mod serial_console;
+#[cfg(not(feature = "minimal"))]
 pub mod text;
+mod time;
+mod watchdog;
+
+/// EFI_ABORTED, returned to the shell when booting fails
+const STATUS_ABORTED: Status = Status(1 << 63 | 21);
 
 fn set_max_mode(output: &uefi::text::TextOutput) -> Result<()> {
     let mut max_i = None;
@@ -48,20 +83,48 @@ fn set_max_mode(output: &uefi::text::TextOutput) -> Result<()> {
     Ok(())
 }
 
-#[no_mangle]
-pub extern "C" fn main() -> Status {
+/// Boot, only returning to go back to the shell after an error
+fn run() -> Status {
     let uefi = std::system_table();
 
-    let _ = (uefi.BootServices.SetWatchdogTimer)(0, 0, 0, ptr::null());
-
+    // The console still works in its current mode
     if let Err(err) = set_max_mode(uefi.ConsoleOut) {
-        println!("Failed to set max mode: {:?}", err);
+        warn!("Failed to set max mode: {:?}", err);
     }
 
     if let Err(err) = arch::main() {
-        println!("App error: {:?}", err);
-        let _ = key::key(true);
+        display::error_screen(&format!("{}", err));
+
+        // Rebooting the machine is obnoxious when testing from the shell
+        if loaded_image::started_from_shell() {
+            watchdog::disarm();
+            return STATUS_ABORTED;
+        }
     }
 
     (uefi.RuntimeServices.ResetSystem)(ResetType::Cold, Status(0), 0, ptr::null());
 }
+
+#[no_mangle]
+pub extern "C" fn main() -> Status {
+    time::start();
+
+    config::load();
+    #[cfg(not(feature = "minimal"))]
+    font::load();
+
+    // Reset if booting stalls, for example on a dead disk
+    watchdog::arm(config::config().watchdog);
+
+    // Print to the serial port instead of the firmware console for the rest of boot
+    #[cfg(target_arch = "x86_64")]
+    {
+        if config::config().console == config::Console::Serial {
+            if let Ok(status) = serial_console::pipe(|| Ok(run())) {
+                return status;
+            }
+        }
+    }
+
+    run()
+}
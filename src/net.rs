@@ -0,0 +1,153 @@
+use core::ptr;
+use std::proto::Protocol;
+use std::vec::Vec;
+use uefi::guid::Guid;
+use uefi::status::{Error, Result, Status};
+
+use crate::config::config;
+
+const TFTP_GET_FILE_SIZE: u32 = 1;
+const TFTP_READ_FILE: u32 = 2;
+
+#[repr(C)]
+#[allow(non_snake_case)]
+pub struct PxeBaseCodeMode {
+    pub Started: bool,
+    pub Ipv6Available: bool,
+    pub Ipv6Supported: bool,
+    pub UsingIpv6: bool,
+    pub BisSupported: bool,
+    pub BisDetected: bool,
+    pub AutoArp: bool,
+    pub SendGUID: bool,
+    pub DhcpDiscoverValid: bool,
+    pub DhcpAckReceived: bool,
+    // The remaining fields are not used
+}
+
+#[repr(C)]
+#[allow(non_snake_case)]
+pub struct PxeBaseCode {
+    pub Revision: u64,
+    pub Start: extern "win64" fn(&mut PxeBaseCode, bool) -> Status,
+    pub Stop: extern "win64" fn(&mut PxeBaseCode) -> Status,
+    pub Dhcp: extern "win64" fn(&mut PxeBaseCode, bool) -> Status,
+    pub Discover: extern "win64" fn(),
+    pub Mtftp: extern "win64" fn(
+        &mut PxeBaseCode,
+        u32, // Operation
+        *mut u8, // BufferPtr
+        bool, // Overwrite
+        &mut u64, // BufferSize
+        *const usize, // BlockSize
+        *const [u8; 16], // ServerIp
+        *const u8, // Filename
+        *const u8, // Info
+        bool, // DontUseBuffer
+    ) -> Status,
+    pub UdpWrite: extern "win64" fn(),
+    pub UdpRead: extern "win64" fn(),
+    pub SetIpFilter: extern "win64" fn(),
+    pub Arp: extern "win64" fn(),
+    pub SetParameters: extern "win64" fn(),
+    pub SetStationIp: extern "win64" fn(),
+    pub SetPackets: extern "win64" fn(),
+    pub Mode: &'static PxeBaseCodeMode,
+}
+
+pub struct Pxe(pub &'static mut PxeBaseCode);
+
+impl Protocol<PxeBaseCode> for Pxe {
+    fn guid() -> Guid {
+        Guid(0x03c4e603, 0xac28, 0x11d3, [0x9a, 0x2d, 0x00, 0x90, 0x27, 0x3f, 0xc1, 0x4d])
+    }
+
+    fn new(inner: &'static mut PxeBaseCode) -> Self {
+        Pxe(inner)
+    }
+}
+
+/// Parse a dotted quad IPv4 address into the PXE address format
+fn parse_ipv4(host: &str) -> Option<[u8; 16]> {
+    let mut ip = [0; 16];
+    let mut parts = host.split('.');
+    for byte in ip.iter_mut().take(4) {
+        *byte = parts.next()?.parse().ok()?;
+    }
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(ip)
+}
+
+/// Fetch a file over the network, using the firmware PXE stack.
+/// Only `tftp://a.b.c.d/path` is supported, as PXE has no HTTP client.
+pub fn fetch(url: &str) -> Result<Vec<u8>> {
+    let rest = if let Some(rest) = url.strip_prefix("tftp://") {
+        rest
+    } else {
+        println!("Unsupported URL {}, only tftp:// is available through PXE", url);
+        return Err(Error::Unsupported);
+    };
+
+    let (host, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i + 1..]),
+        None => (rest, ""),
+    };
+    let server_ip = parse_ipv4(host).ok_or_else(|| {
+        println!("Invalid TFTP server {}, expected an IPv4 address", host);
+        Error::InvalidParameter
+    })?;
+    let mut filename = Vec::with_capacity(path.len() + 1);
+    filename.extend(path.bytes());
+    filename.push(0);
+
+    let mut pxe = Pxe::one()?;
+
+    if !pxe.0.Mode.Started {
+        (pxe.0.Start)(pxe.0, false)?;
+    }
+    // The firmware usually did DHCP already when network booting
+    if !pxe.0.Mode.DhcpAckReceived {
+        println!("Running DHCP");
+        (pxe.0.Dhcp)(pxe.0, true)?;
+    }
+
+    let mut size = 0;
+    (pxe.0.Mtftp)(
+        pxe.0,
+        TFTP_GET_FILE_SIZE,
+        ptr::null_mut(),
+        false,
+        &mut size,
+        ptr::null(),
+        &server_ip,
+        filename.as_ptr(),
+        ptr::null(),
+        false
+    )?;
+
+    println!("Fetching {} ({} bytes)", url, size);
+
+    if size > config().max_kernel_size {
+        println!("{} is larger than the maximum of {} bytes", url, config().max_kernel_size);
+        return Err(Error::BadBufferSize);
+    }
+
+    let mut data = vec![0; size as usize];
+    (pxe.0.Mtftp)(
+        pxe.0,
+        TFTP_READ_FILE,
+        data.as_mut_ptr(),
+        false,
+        &mut size,
+        ptr::null(),
+        &server_ip,
+        filename.as_ptr(),
+        ptr::null(),
+        false
+    )?;
+    data.truncate(size as usize);
+
+    Ok(data)
+}
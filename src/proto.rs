@@ -0,0 +1,95 @@
+use core::{mem, ptr};
+use core::ops::{Deref, DerefMut, Try};
+use std::proto::Protocol;
+use std::vec::Vec;
+use uefi::Handle;
+use uefi::boot::LocateSearchType;
+use uefi::guid::Guid;
+use uefi::status::Result;
+
+/// `OpenProtocol` attribute that records the open, like `HandleProtocol` does implicitly
+const OPEN_PROTOCOL_BY_HANDLE_PROTOCOL: u32 = 0x01;
+
+/// Find all handles supporting a protocol, however many there are
+pub fn locate_handles(guid: &Guid) -> Result<Vec<Handle>> {
+    let uefi = std::system_table();
+
+    // The first call fails with the buffer size that is needed
+    let mut size = 0;
+    let _ = (uefi.BootServices.LocateHandle)(LocateSearchType::ByProtocol, guid, 0, &mut size, ptr::null_mut());
+    if size == 0 {
+        return Ok(Vec::new());
+    }
+
+    let mut handles = vec![Handle(0); size / mem::size_of::<Handle>()];
+    size = handles.len() * mem::size_of::<Handle>();
+    (uefi.BootServices.LocateHandle)(LocateSearchType::ByProtocol, guid, 0, &mut size, handles.as_mut_ptr())?;
+    handles.truncate(size / mem::size_of::<Handle>());
+
+    Ok(handles)
+}
+
+/// Check if `handle` supports the protocol with `guid`
+pub fn has_protocol(handle: Handle, guid: &Guid) -> bool {
+    let uefi = std::system_table();
+
+    let mut interface = 0;
+    (uefi.BootServices.HandleProtocol)(handle, guid, &mut interface).branch().is_continue()
+}
+
+/// A protocol opened with `OpenProtocol` by the bootloader, which is closed when dropped
+pub struct OpenProtocol<P> {
+    handle: Handle,
+    guid: Guid,
+    inner: P,
+}
+
+impl<P> OpenProtocol<P> {
+    pub fn open<T: 'static>(handle: Handle) -> Result<Self> where P: Protocol<T> {
+        let uefi = std::system_table();
+
+        let guid = P::guid();
+        let mut interface = 0;
+        (uefi.BootServices.OpenProtocol)(
+            handle,
+            &guid,
+            &mut interface,
+            std::handle(),
+            Handle(0),
+            OPEN_PROTOCOL_BY_HANDLE_PROTOCOL
+        )?;
+
+        Ok(Self {
+            handle,
+            guid,
+            inner: P::new(unsafe { &mut *(interface as *mut T) }),
+        })
+    }
+
+    /// Keep the protocol open for the rest of boot, for protocols that are handed off
+    pub fn leak(self) -> P {
+        let this = mem::ManuallyDrop::new(self);
+        unsafe { ptr::read(&this.inner) }
+    }
+}
+
+impl<P> Deref for OpenProtocol<P> {
+    type Target = P;
+
+    fn deref(&self) -> &P {
+        &self.inner
+    }
+}
+
+impl<P> DerefMut for OpenProtocol<P> {
+    fn deref_mut(&mut self) -> &mut P {
+        &mut self.inner
+    }
+}
+
+impl<P> Drop for OpenProtocol<P> {
+    fn drop(&mut self) {
+        let uefi = std::system_table();
+        let _ = (uefi.BootServices.CloseProtocol)(self.handle, &self.guid, std::handle(), Handle(0));
+    }
+}
@@ -0,0 +1,193 @@
+//! A seed for the kernel's random number generator, from the best source that works
+
+use core::ops::Try;
+use core::ptr;
+use std::boxed::Box;
+use std::proto::Protocol;
+use uefi::guid::Guid;
+use uefi::status::Status;
+use uefi::time::Time;
+
+use crate::loaded_image::LoadedImage;
+use crate::secret::Secret;
+use crate::time;
+
+/// Bytes in a seed
+pub const SEED_SIZE: usize = 32;
+
+/// The seed mixes counters, the time, and addresses, and has little entropy. The kernel
+/// should gather more before relying on it.
+pub const QUALITY_LOW: u8 = 0;
+/// The seed comes from the jitter of the CPU counter
+pub const QUALITY_TIMER: u8 = 1;
+/// The seed comes from the firmware RNG protocol
+pub const QUALITY_FIRMWARE: u8 = 2;
+
+/// Counter samples taken for the timer jitter seed
+const TIMER_SAMPLES: usize = 256;
+
+#[repr(C)]
+#[allow(non_snake_case)]
+pub struct RngData {
+    pub GetInfo: extern "win64" fn(&RngData, &mut usize, *mut Guid) -> Status,
+    pub GetRNG: extern "win64" fn(&RngData, *const Guid, usize, *mut u8) -> Status,
+}
+
+/// EFI_RNG_PROTOCOL
+pub struct Rng(pub &'static mut RngData);
+
+impl Protocol<RngData> for Rng {
+    fn guid() -> Guid {
+        Guid(0x3152bca5, 0xeade, 0x433d, [0x86, 0x2e, 0xc0, 0x1c, 0xdc, 0x29, 0x1f, 0x44])
+    }
+
+    fn new(inner: &'static mut RngData) -> Self {
+        Self(inner)
+    }
+}
+
+/// Spreads every bit of the input over the whole seed. This is not a cryptographic hash,
+/// it only keeps the entropy that is there.
+struct Mixer {
+    state: [u64; SEED_SIZE / 8],
+    count: usize,
+}
+
+impl Mixer {
+    fn new() -> Self {
+        Self { state: [0x243F_6A88_85A3_08D3, 0x1319_8A2E_0370_7344, 0xA409_3822_299F_31D0, 0x082E_FA98_EC4E_6C89], count: 0 }
+    }
+
+    /// The SplitMix64 finalizer
+    fn mix(mut x: u64) -> u64 {
+        x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        x ^ (x >> 31)
+    }
+
+    fn absorb(&mut self, value: u64) {
+        let lanes = self.state.len();
+        let lane = self.count % lanes;
+        let previous = self.state[(lane + lanes - 1) % lanes];
+        self.state[lane] = Self::mix(self.state[lane] ^ value ^ previous);
+        self.count += 1;
+    }
+
+    fn finish(mut self) -> [u8; SEED_SIZE] {
+        // Another round, so every lane depends on every input
+        for _ in 0..self.state.len() {
+            self.absorb(self.count as u64);
+        }
+
+        let mut seed = [0; SEED_SIZE];
+        for (chunk, lane) in seed.chunks_exact_mut(8).zip(self.state.iter()) {
+            chunk.copy_from_slice(&lane.to_le_bytes());
+        }
+        seed
+    }
+}
+
+fn firmware_seed() -> Option<[u8; SEED_SIZE]> {
+    let rng = Rng::one().ok()?;
+    let mut seed = [0; SEED_SIZE];
+    // A null algorithm lets the firmware pick its default
+    let status = (rng.0.GetRNG)(rng.0, ptr::null(), seed.len(), seed.as_mut_ptr());
+    if status.branch().is_break() {
+        warn!("Firmware RNG failed: {:?}", status);
+        return None;
+    }
+    // Broken firmware may report success without writing anything
+    if seed.iter().all(|&byte| byte == 0) {
+        warn!("Firmware RNG returned all zeroes");
+        return None;
+    }
+    Some(seed)
+}
+
+/// Sample the CPU counter around short stalls, `None` if it does not jitter
+fn timer_seed() -> Option<[u8; SEED_SIZE]> {
+    let uefi = std::system_table();
+
+    let mut mixer = Mixer::new();
+    let mut last = time::ticks();
+    let mut first_delta = None;
+    let mut varies = false;
+    for _ in 0..TIMER_SAMPLES {
+        let _ = (uefi.BootServices.Stall)(1);
+        let now = time::ticks();
+        let delta = now.wrapping_sub(last);
+        match first_delta {
+            None => first_delta = Some(delta),
+            Some(first) => varies |= delta != first,
+        }
+        mixer.absorb(now);
+        last = now;
+    }
+
+    // A counter that stands still, or advances by the same amount every time, has no entropy
+    if first_delta == Some(0) || !varies {
+        warn!("CPU counter does not jitter, it can not seed the RNG");
+        return None;
+    }
+    Some(mixer.finish())
+}
+
+/// Mix everything that differs somewhat between boots, as a last resort
+fn low_seed() -> [u8; SEED_SIZE] {
+    let uefi = std::system_table();
+
+    let mut mixer = Mixer::new();
+    mixer.absorb(time::ticks());
+
+    let mut count = 0;
+    if (uefi.BootServices.GetNextMonotonicCount)(&mut count).branch().is_continue() {
+        mixer.absorb(count);
+    }
+
+    let mut now = Time::default();
+    if (uefi.RuntimeServices.GetTime)(&mut now, ptr::null_mut()).branch().is_continue() {
+        mixer.absorb(now.Year as u64 | (now.Month as u64) << 16 | (now.Day as u64) << 24 | (now.Hour as u64) << 32);
+        mixer.absorb(now.Minute as u64 | (now.Second as u64) << 8 | (now.Nanosecond as u64) << 16);
+    }
+
+    // Firmware allocations, and where the bootloader was loaded, vary with the hardware
+    let mut map_size = 0;
+    let mut map_key = 0;
+    let mut descriptor_size = 0;
+    let mut descriptor_version = 0;
+    let _ = (uefi.BootServices.GetMemoryMap)(
+        &mut map_size,
+        ptr::null_mut(),
+        &mut map_key,
+        &mut descriptor_size,
+        &mut descriptor_version
+    );
+    mixer.absorb(map_size as u64);
+    if let Some(image) = LoadedImage::current() {
+        mixer.absorb(image.0.ImageBase as u64);
+    }
+    let heap = Box::new(0u8);
+    mixer.absorb(&*heap as *const u8 as u64);
+    mixer.absorb(&count as *const u64 as u64);
+
+    mixer.finish()
+}
+
+/// Get a seed and its quality, trying the firmware RNG, then the CPU counter, then
+/// everything else. The seed is never all zeroes, and is zeroed once dropped.
+pub fn seed() -> (Secret, u8) {
+    let (mut seed, quality) = if let Some(seed) = firmware_seed() {
+        (seed, QUALITY_FIRMWARE)
+    } else if let Some(seed) = timer_seed() {
+        (seed, QUALITY_TIMER)
+    } else {
+        warn!("No entropy source found, the RNG seed has low entropy");
+        (low_seed(), QUALITY_LOW)
+    };
+
+    if seed.iter().all(|&byte| byte == 0) {
+        warn!("RNG seed is all zeroes, replacing it");
+        seed = Mixer::new().finish();
+    }
+    (Secret::take(&mut seed), quality)
+}
@@ -0,0 +1,69 @@
+//! Buffers for secrets that are scrubbed before the kernel runs. This is only used for the
+//! RNG seed, once it has been copied to the kernel. ExitBootServices leaves the heap as it
+//! is, so anything not zeroed here could be recovered by the kernel or later code.
+
+use core::{ptr, slice};
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{compiler_fence, Ordering};
+use std::boxed::Box;
+use std::vec::Vec;
+
+/// Address and length of every `Secret` that is still alive
+static mut LIVE: Vec<(usize, usize)> = Vec::new();
+
+/// Overwrite `data` with zeroes, in a way that is not optimized out
+pub fn zeroize(data: &mut [u8]) {
+    for byte in data.iter_mut() {
+        unsafe { ptr::write_volatile(byte, 0); }
+    }
+    compiler_fence(Ordering::SeqCst);
+}
+
+/// A fixed size buffer that is zeroed when dropped, or by `scrub_all` if it is still alive
+pub struct Secret(Box<[u8]>);
+
+impl Secret {
+    pub fn new(len: usize) -> Self {
+        let data = vec![0; len].into_boxed_slice();
+        unsafe { LIVE.push((data.as_ptr() as usize, data.len())); }
+        Secret(data)
+    }
+
+    /// Move `data` into a new secret, zeroing the original
+    pub fn take(data: &mut [u8]) -> Self {
+        let mut secret = Self::new(data.len());
+        secret.copy_from_slice(data);
+        zeroize(data);
+        secret
+    }
+}
+
+impl Deref for Secret {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl DerefMut for Secret {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        zeroize(&mut self.0);
+        let address = self.0.as_ptr() as usize;
+        unsafe { LIVE.retain(|&(live, _)| live != address); }
+    }
+}
+
+/// Zero every secret that is still alive. Call right before exiting boot services, this
+/// does not allocate so the memory map key stays valid.
+pub unsafe fn scrub_all() {
+    for &(address, len) in LIVE.iter() {
+        zeroize(slice::from_raw_parts_mut(address as *mut u8, len));
+    }
+}
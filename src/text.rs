@@ -1,4 +1,4 @@
-use core::{char, mem, ptr};
+use core::{char, cmp, mem, slice};
 use core::ops::Deref;
 use orbclient::{Color, Renderer};
 use std::boxed::Box;
@@ -6,10 +6,68 @@ use std::proto::Protocol;
 use uefi::Handle;
 use uefi::boot::InterfaceType;
 use uefi::guid::SIMPLE_TEXT_OUTPUT_GUID;
-use uefi::status::{Result, Status};
+use uefi::status::{Error, Result, Status};
 use uefi::text::TextOutputMode;
+use util::text::{Cells, Terminal, Written};
 
-use crate::display::{Display, ScaledDisplay, Output};
+use crate::display::{Backend, Display, ScaledDisplay};
+use crate::font;
+
+/// Space left on each side of the text region, in pixels
+const MARGIN: i32 = 16;
+/// Space left below the text region, for the prompt
+const MARGIN_BOTTOM: i32 = 64;
+/// Fewest columns and rows of the text region, unless the display is even smaller
+const MIN_COLS: i32 = 40;
+const MIN_ROWS: i32 = 4;
+
+/// Fit a text region below `top` on a display of `width` by `height` pixels, returning its
+/// x offset, columns, and rows
+pub fn text_area(width: u32, height: u32, top: i32) -> (i32, usize, usize) {
+    let (width, height) = (width as i32, height as i32);
+
+    let cols = cmp::max((width - 2 * MARGIN) / 8, cmp::min(MIN_COLS, width / 8));
+    let space = cmp::max(height - top, 0);
+    let rows = cmp::max((space - MARGIN_BOTTOM - 1) / 16, cmp::min(MIN_ROWS, space / 16));
+
+    ((width - cols * 8) / 2, cols as usize, rows as usize)
+}
+
+/// The text region of a display, as cells for the terminal to draw into
+struct TextCells<'b, 'a> {
+    display: &'b mut ScaledDisplay<'a>,
+    off_x: i32,
+    off_y: i32,
+    cols: usize,
+    rows: usize,
+}
+
+impl<'b, 'a> TextCells<'b, 'a> {
+    fn pos(&self, column: i32, row: i32) -> (i32, i32) {
+        (column * 8 + self.off_x, row * 16 + self.off_y)
+    }
+}
+
+impl<'b, 'a> Cells for TextCells<'b, 'a> {
+    fn clear(&mut self, column: i32, row: i32) {
+        let (x, y) = self.pos(column, row);
+        self.display.rect(x, y, 8, 16, Color::rgb(0, 0, 0));
+    }
+
+    fn glyph(&mut self, column: i32, row: i32, c: char, fg: u32) {
+        let (x, y) = self.pos(column, row);
+        font::draw(&mut *self.display, x, y, c, Color { data: fg });
+    }
+
+    /// Move the text region up by one row in the back buffer, clearing the last row
+    fn scroll(&mut self) {
+        if self.rows > 0 {
+            let w = self.cols as u32 * 8;
+            self.display.copy_rect(self.off_x, self.off_y + 16, w, (self.rows as u32 - 1) * 16, self.off_x, self.off_y);
+            self.display.rect(self.off_x, self.off_y + (self.rows as i32 - 1) * 16, w, 16, Color::rgb(0, 0, 0));
+        }
+    }
+}
 
 #[repr(C)]
 #[allow(non_snake_case)]
@@ -30,6 +88,7 @@ pub struct TextDisplay<'a> {
     pub off_y: i32,
     pub cols: usize,
     pub rows: usize,
+    term: Terminal,
     pub display: ScaledDisplay<'a>,
 }
 
@@ -107,6 +166,7 @@ impl<'a> TextDisplay<'a> {
             off_y: 0,
             cols,
             rows,
+            term: Terminal::new(cols, rows),
             display,
         }
     }
@@ -126,87 +186,63 @@ impl<'a> TextDisplay<'a> {
         self.display.sync();
     }
 
-    pub fn scroll(&mut self, color: Color) {
-        if self.rows > 0 {
-            let w = self.display.width();
-
-            let dst = self.off_y * w as i32;
-            let src = (self.off_y + 16) * w as i32;
-            let len = (self.rows - 1) * 16 * w as usize;
-            unsafe {
-                let scale = self.display.scale() as isize;
-                let data_ptr = self.display.data_mut().as_mut_ptr() as *mut u32;
-                ptr::copy(
-                    data_ptr.offset(src as isize * scale * scale),
-                    data_ptr.offset(dst as isize * scale * scale),
-                    len * (scale * scale) as usize);
-            }
-
-            self.display.rect(self.off_x, self.off_y + (self.rows as i32 - 1) * 16, self.cols as u32 * 8, 16, color);
+    pub fn set_cursor_pos(&mut self, column: i32, row: i32) {
+        self.mode.CursorColumn = column;
+        if row >= 0 && (row as usize) < self.rows {
+            self.mode.CursorRow = row;
         }
-    }
 
-    pub fn set_cursor_pos(&mut self, column: i32, _row: i32) {
-        self.mode.CursorColumn = column;
+        if self.mode.CursorVisible {
+            // Underline the cell at the cursor
+            let (x, y) = self.pos();
+            self.display.rect(x, y + 14, 8, 2, Color::rgb(255, 255, 255));
+            let w = self.display.width();
+            self.display.blit(0, y, w, 16);
+        }
     }
 
     pub fn write(&mut self, string: *const u16) {
-        let bg = Color::rgb(0, 0, 0);
-        let fg = Color::rgb(255, 255, 255);
-
-        let mut scrolled = false;
-        let mut changed = false;
         let (_sx, sy) = self.pos();
 
-        let mut i = 0;
-        loop {
-            let w = unsafe { *string.offset(i) };
-            if w == 0 {
-                break;
-            }
-
-            let c = unsafe { char::from_u32_unchecked(w as u32) };
-
-            if self.mode.CursorColumn as usize >= self.cols {
-                self.mode.CursorColumn = 0;
-                self.mode.CursorRow += 1;
-            }
-
-            while self.mode.CursorRow as usize >= self.rows {
-                self.scroll(bg);
-                self.mode.CursorRow -= 1;
-                scrolled = true;
-            }
-
-            match c {
-                '\x08' => if self.mode.CursorColumn > 0 {
-                    let (x, y) = self.pos();
-                    self.display.rect(x, y, 8, 16, bg);
-                    self.mode.CursorColumn -= 1;
-                    changed = true;
-                },
-                '\r'=> {
-                    self.mode.CursorColumn = 0;
-                },
-                '\n' => {
-                    self.mode.CursorRow += 1;
-                },
-                _ => {
-                    let (x, y) = self.pos();
-                    self.display.rect(x, y, 8, 16, bg);
-                    self.display.char(x, y, c, fg);
-                    self.mode.CursorColumn += 1;
-                    changed = true;
-                }
-            }
-
-            i += 1;
+        let mut len = 0;
+        while unsafe { *string.add(len) } != 0 {
+            len += 1;
         }
-
-        if scrolled {
+        let string = unsafe { slice::from_raw_parts(string, len) };
+        let chars = char::decode_utf16(string.iter().cloned())
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER));
+
+        self.term.column = self.mode.CursorColumn;
+        self.term.row = self.mode.CursorRow;
+        self.term.cols = self.cols;
+        self.term.rows = self.rows;
+
+        let mut cells = TextCells {
+            display: &mut self.display,
+            off_x: self.off_x,
+            off_y: self.off_y,
+            cols: self.cols,
+            rows: self.rows,
+        };
+        let Written { scrolled, changed } = self.term.write(&mut cells, chars);
+
+        self.mode.CursorColumn = self.term.column;
+        self.mode.CursorRow = self.term.row;
+
+        if scrolled > 0 {
             let (cx, cw) = (0, self.display.width() as i32);
-            let (cy, ch) = (self.off_y, self.rows as u32 * 16);
-            self.display.blit(cx, cy, cw as u32, ch as u32);
+            let (cy, ch) = (self.off_y, self.rows as i32 * 16);
+            // The screen matched the back buffer before this write, so move what is still
+            // visible on the screen itself, and only redraw from the first changed row
+            let shift = scrolled as i32 * 16;
+            let first = if shift < ch && self.display.copy_screen_rect(
+                self.off_x, cy + shift, self.cols as u32 * 8, (ch - shift) as u32, self.off_x, cy
+            ) {
+                cmp::max(cy, sy - shift)
+            } else {
+                cy
+            };
+            self.display.blit(cx, first, cw as u32, (cy + ch - first) as u32);
         } else if changed {
             let (_x, y) = self.pos();
             let (cx, cw) = (0, self.display.width() as i32);
@@ -246,7 +282,10 @@ impl<'a> TextDisplay<'a> {
 }
 
 pub fn pipe<T, F: FnMut() -> Result<T>>(f: F) -> Result<T> {
-    let mut output = Output::one()?;
-    let mut display = Display::new(&mut output);
+    let mut backend = Backend::one();
+    if !backend.is_graphical() {
+        return Err(Error::Unsupported);
+    }
+    let mut display = Display::new(&mut backend);
     TextDisplay::new(ScaledDisplay::new(&mut display)).pipe(f)
 }
@@ -0,0 +1,88 @@
+static mut START: u64 = 0;
+static mut FREQUENCY: u64 = 0;
+
+/// Read the free-running CPU counter
+#[cfg(target_arch = "x86_64")]
+pub fn ticks() -> u64 {
+    unsafe { x86::time::rdtsc() }
+}
+
+/// Read the free-running CPU counter
+#[cfg(target_arch = "aarch64")]
+pub fn ticks() -> u64 {
+    let ticks: u64;
+    unsafe { asm!("mrs {}, cntvct_el0", out(reg) ticks); }
+    ticks
+}
+
+/// Record the time the bootloader started
+pub fn start() {
+    unsafe { START = ticks(); }
+}
+
+/// The counter value recorded by `start`
+pub fn start_ticks() -> u64 {
+    unsafe { START }
+}
+
+#[cfg(target_arch = "aarch64")]
+fn firmware_frequency() -> Option<u64> {
+    let frequency: u64;
+    unsafe { asm!("mrs {}, cntfrq_el0", out(reg) frequency); }
+    if frequency > 0 {
+        Some(frequency)
+    } else {
+        None
+    }
+}
+
+/// Get the TSC frequency from the TSC/crystal clock ratio in CPUID leaf 0x15
+#[cfg(target_arch = "x86_64")]
+fn firmware_frequency() -> Option<u64> {
+    use core::arch::x86_64::__cpuid;
+
+    unsafe {
+        if __cpuid(0).eax < 0x15 {
+            return None;
+        }
+
+        let leaf = __cpuid(0x15);
+        let (denominator, numerator, crystal) = (leaf.eax as u64, leaf.ebx as u64, leaf.ecx as u64);
+        // Many CPUs report the ratio but not the crystal frequency
+        if denominator == 0 || numerator == 0 || crystal == 0 {
+            return None;
+        }
+        Some(crystal * numerator / denominator)
+    }
+}
+
+/// Measure the counter against a 10 ms firmware stall. This relies on boot services, and is
+/// only as accurate as the firmware's Stall, typically within a few percent.
+fn calibrate() -> u64 {
+    let uefi = std::system_table();
+
+    let before = ticks();
+    let _ = (uefi.BootServices.Stall)(10_000);
+    let after = ticks();
+
+    after.wrapping_sub(before) * 100
+}
+
+/// Counter ticks per second. The first call may calibrate using boot services, so make it before they exit.
+pub fn frequency() -> u64 {
+    unsafe {
+        if FREQUENCY == 0 {
+            FREQUENCY = firmware_frequency().unwrap_or_else(calibrate);
+        }
+        FREQUENCY
+    }
+}
+
+/// Milliseconds since `start`
+pub fn elapsed_ms() -> u64 {
+    let frequency = frequency();
+    if frequency == 0 {
+        return 0;
+    }
+    ticks().wrapping_sub(start_ticks()) * 1000 / frequency
+}
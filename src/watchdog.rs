@@ -0,0 +1,39 @@
+use core::ptr;
+
+/// Watchdog codes below this are reserved for firmware use
+const WATCHDOG_CODE: u64 = 0x10000;
+
+static mut TIMEOUT: usize = 0;
+
+fn set(secs: usize) {
+    let uefi = std::system_table();
+    let _ = (uefi.BootServices.SetWatchdogTimer)(secs, WATCHDOG_CODE, 0, ptr::null());
+}
+
+/// Arm the firmware watchdog, which resets the machine unless disarmed within `secs` seconds.
+/// An argument of zero disarms it instead.
+pub fn arm(secs: usize) {
+    unsafe { TIMEOUT = secs; }
+    set(secs);
+}
+
+/// Disarm the watchdog, which must happen before the kernel takes over
+pub fn disarm() {
+    unsafe { TIMEOUT = 0; }
+    set(0);
+}
+
+/// Stop the watchdog while waiting on the user, without forgetting its timeout
+pub fn suspend() {
+    if unsafe { TIMEOUT } > 0 {
+        set(0);
+    }
+}
+
+/// Restart the watchdog with its full timeout after `suspend`
+pub fn resume() {
+    let secs = unsafe { TIMEOUT };
+    if secs > 0 {
+        set(secs);
+    }
+}
@@ -0,0 +1,156 @@
+//! Recognize and decompress compressed kernels
+
+use alloc::vec::Vec;
+use miniz_oxide::inflate::TINFLStatus;
+
+pub const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+pub const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+/// Formats that are not decompressed, only recognized so they are not booted as is
+const XZ_MAGIC: [u8; 6] = [0xfd, b'7', b'z', b'X', b'Z', 0];
+const BZIP2_MAGIC: [u8; 3] = [b'B', b'Z', b'h'];
+const LZ4_MAGIC: [u8; 4] = [0x04, 0x22, 0x4d, 0x18];
+
+/// Why a gzip member could not be decompressed
+#[derive(Debug)]
+pub enum GzipError {
+    /// The header is truncated, or the method is not deflate
+    Header,
+    /// The deflate stream is corrupt or truncated
+    Inflate(TINFLStatus),
+}
+
+/// Find the start of the deflate stream in a gzip member
+fn gzip_payload(data: &[u8]) -> Option<&[u8]> {
+    const FHCRC: u8 = 1 << 1;
+    const FEXTRA: u8 = 1 << 2;
+    const FNAME: u8 = 1 << 3;
+    const FCOMMENT: u8 = 1 << 4;
+
+    // Only deflate is defined
+    if *data.get(2)? != 8 {
+        return None;
+    }
+    let flags = *data.get(3)?;
+
+    let mut i = 10;
+    if flags & FEXTRA != 0 {
+        let len = *data.get(i)? as usize | (*data.get(i + 1)? as usize) << 8;
+        i += 2 + len;
+    }
+    if flags & FNAME != 0 {
+        i += data.get(i..)?.iter().position(|&b| b == 0)? + 1;
+    }
+    if flags & FCOMMENT != 0 {
+        i += data.get(i..)?.iter().position(|&b| b == 0)? + 1;
+    }
+    if flags & FHCRC != 0 {
+        i += 2;
+    }
+
+    data.get(i..)
+}
+
+/// Decompress a gzip member, ignoring the trailer
+pub fn gunzip(data: &[u8]) -> Result<Vec<u8>, GzipError> {
+    let payload = gzip_payload(data).ok_or(GzipError::Header)?;
+    miniz_oxide::inflate::decompress_to_vec(payload).map_err(GzipError::Inflate)
+}
+
+/// UEFI compressed data has no magic, only a header with the compressed and original sizes.
+/// Check that the compressed size matches the data, allowing padding to 8 bytes.
+pub fn is_efi_compressed(data: &[u8]) -> bool {
+    if data.len() < 8 || data.starts_with(&ELF_MAGIC) {
+        return false;
+    }
+    let compressed = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    let original = u32::from_le_bytes([data[4], data[5], data[6], data[7]]);
+    original > 0 && data.len() >= compressed + 8 && data.len() - (compressed + 8) < 8
+}
+
+/// Check if `data` starts with the magic of a compression format, decompressed or not
+pub fn has_magic(data: &[u8]) -> bool {
+    data.starts_with(&GZIP_MAGIC)
+        || data.starts_with(&ZSTD_MAGIC)
+        || data.starts_with(&XZ_MAGIC)
+        || data.starts_with(&BZIP2_MAGIC)
+        || data.starts_with(&LZ4_MAGIC)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Wrap a deflate stream in a gzip member with a file name, without the trailer
+    fn gzip_member(data: &[u8]) -> Vec<u8> {
+        let mut member = vec![0x1f, 0x8b, 8, 1 << 3, 0, 0, 0, 0, 0, 3];
+        member.extend_from_slice(b"kernel\0");
+        member.extend(miniz_oxide::deflate::compress_to_vec(data, 6));
+        member
+    }
+
+    #[test]
+    fn gzip_round_trip() {
+        let data: Vec<u8> = (0..4096).map(|i| (i % 251) as u8).collect();
+        let member = gzip_member(&data);
+        assert!(member.starts_with(&GZIP_MAGIC));
+        assert!(member.len() < data.len());
+        assert_eq!(gunzip(&member).unwrap(), data);
+    }
+
+    #[test]
+    fn gzip_bad_header() {
+        // Method 0 is not deflate
+        assert!(matches!(gunzip(&[0x1f, 0x8b, 0, 0, 0, 0, 0, 0, 0, 3]), Err(GzipError::Header)));
+        // The file name runs off the end
+        assert!(matches!(gunzip(&[0x1f, 0x8b, 8, 1 << 3, 0, 0, 0, 0, 0, 3, b'k']), Err(GzipError::Header)));
+    }
+
+    #[test]
+    fn gzip_truncated() {
+        let data: Vec<u8> = (0..4096).map(|i| (i % 251) as u8).collect();
+        let member = gzip_member(&data);
+        assert!(matches!(gunzip(&member[..member.len() / 2]), Err(GzipError::Inflate(_))));
+    }
+
+    /// A UEFI compression header for `compressed` bytes of `original` bytes, and the data
+    fn efi_member(compressed: u32, original: u32, len: usize) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&compressed.to_le_bytes());
+        data.extend_from_slice(&original.to_le_bytes());
+        data.resize(len, 0xaa);
+        data
+    }
+
+    #[test]
+    fn efi_sizes_match() {
+        assert!(is_efi_compressed(&efi_member(100, 400, 108)));
+        // Padded to 8 bytes
+        assert!(is_efi_compressed(&efi_member(100, 400, 115)));
+    }
+
+    #[test]
+    fn efi_sizes_do_not_match() {
+        assert!(!is_efi_compressed(&efi_member(100, 400, 116)));
+        assert!(!is_efi_compressed(&efi_member(100, 400, 107)));
+        assert!(!is_efi_compressed(&efi_member(100, 0, 108)));
+        assert!(!is_efi_compressed(&[0; 7]));
+    }
+
+    #[test]
+    fn compression_magics() {
+        assert!(has_magic(&gzip_member(b"kernel")));
+        assert!(has_magic(&[0x28, 0xb5, 0x2f, 0xfd, 0]));
+        assert!(has_magic(b"\xfd7zXZ\0\0"));
+        assert!(has_magic(b"BZh91AY"));
+        assert!(has_magic(&[0x04, 0x22, 0x4d, 0x18, 0]));
+    }
+
+    #[test]
+    fn raw_kernels_have_no_magic() {
+        assert!(!has_magic(b"\x7fELF\x02\x01\x01"));
+        assert!(!has_magic(&[0; 64]));
+        assert!(!has_magic(&[0x1f]));
+        assert!(!has_magic(b"BZ"));
+    }
+}
@@ -0,0 +1,74 @@
+//! Addressing the boot disk
+
+use core::cmp;
+
+/// Bytes read ahead of a small read, enough for a run of metadata blocks.
+/// Reads this large or larger, like the 4 MiB kernel chunks, bypass the read-ahead.
+pub const PREFETCH_SIZE: usize = 256 * 1024;
+
+/// Get the firmware LBA of a filesystem block, for a filesystem with `fs_block_size` byte
+/// blocks on a disk with `disk_block_size` byte blocks. This is all u64, so blocks past
+/// 4 GiB are not truncated.
+pub fn block_lba(block: u64, fs_block_size: u64, disk_block_size: u64) -> u64 {
+    block * fs_block_size / disk_block_size
+}
+
+/// Get the bytes to read ahead for a read of `len` bytes at byte `offset`, clipped to whole
+/// firmware blocks of a disk of `disk_size` bytes, or `None` to read directly instead
+pub fn prefetch_len(len: usize, offset: u64, disk_size: u64, block_size: u64) -> Option<u64> {
+    if len >= PREFETCH_SIZE {
+        return None;
+    }
+    let window = cmp::min(PREFETCH_SIZE as u64, disk_size.saturating_sub(offset));
+    let window = window / block_size * block_size;
+    if window < len as u64 {
+        return None;
+    }
+    Some(window)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Block size of RedoxFS
+    const FS_BLOCK: u64 = 4096;
+    /// Filesystem block holding the byte at 4 GiB
+    const BLOCK_4G: u64 = 0x1_0000_0000 / FS_BLOCK;
+
+    #[test]
+    fn lba_past_4g() {
+        assert_eq!(block_lba(BLOCK_4G, FS_BLOCK, 512), 0x80_0000);
+        assert_eq!(block_lba(BLOCK_4G + 1, FS_BLOCK, 512), 0x80_0008);
+        assert_eq!(block_lba(BLOCK_4G + 1, FS_BLOCK, 4096), 0x10_0001);
+        assert_eq!(block_lba(BLOCK_4G + 1, FS_BLOCK, 512) * 512, 0x1_0000_1000);
+    }
+
+    #[test]
+    fn lba_past_2t() {
+        // LBAs past u32::MAX, as on disks over 2 TiB with 512 byte blocks
+        let block = 0x200_0000_0000 / FS_BLOCK;
+        assert_eq!(block_lba(block, FS_BLOCK, 512), 0x1_0000_0000);
+    }
+
+    #[test]
+    fn metadata_reads_ahead() {
+        let disk_size = 1024 * 1024 * 1024;
+        assert_eq!(prefetch_len(FS_BLOCK as usize, 0, disk_size, 512), Some(PREFETCH_SIZE as u64));
+    }
+
+    #[test]
+    fn large_reads_bypass() {
+        let disk_size = 1024 * 1024 * 1024;
+        assert_eq!(prefetch_len(4 * 1024 * 1024, 0, disk_size, 512), None);
+        assert_eq!(prefetch_len(PREFETCH_SIZE, 0, disk_size, 512), None);
+    }
+
+    #[test]
+    fn window_stops_at_disk_end() {
+        let disk_size = 1024 * 1024;
+        let offset = disk_size - 2 * FS_BLOCK;
+        assert_eq!(prefetch_len(FS_BLOCK as usize, offset, disk_size, 4096), Some(2 * FS_BLOCK));
+        assert_eq!(prefetch_len(FS_BLOCK as usize, disk_size, disk_size, 4096), None);
+    }
+}
@@ -0,0 +1,485 @@
+//! Read-only ext2, ext3, and ext4 support, enough to load a kernel from a Linux partition
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::{cmp, fmt, result};
+
+/// Size of the blocks a `Disk` is read in, which is the RedoxFS block size
+pub const BLOCK_SIZE: u64 = 4096;
+
+/// Superblock magic, at byte 0x438 of the filesystem
+pub const MAGIC: u16 = 0xEF53;
+
+const SUPERBLOCK_OFFSET: u64 = 1024;
+const ROOT_INODE: u32 = 2;
+
+/// Group descriptors are 64 bytes instead of 32
+const INCOMPAT_64BIT: u32 = 0x80;
+/// The inode maps its data with an extent tree instead of block lists
+const EXTENTS_FL: u32 = 0x80000;
+const EXTENT_MAGIC: u16 = 0xF30A;
+/// Extents longer than this are uninitialized, and read as zeroes
+const EXTENT_INIT_MAX_LEN: u16 = 32768;
+
+/// Largest directory that is read, far more than the entries on a boot path need
+const MAX_DIR_SIZE: u64 = 16 * 1024 * 1024;
+
+const S_IFMT: u16 = 0xF000;
+const S_IFDIR: u16 = 0x4000;
+
+/// Why the filesystem could not be read
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    /// The disk could not be read
+    Device,
+    /// The superblock or a block map is damaged
+    Corrupt,
+    /// The block size, `1024 << log`, is over 64 KiB
+    BlockSize(u32),
+    /// A directory is larger than the filesystem or `MAX_DIR_SIZE`
+    DirSize(u64),
+    /// A path goes through an inode that is not a directory
+    NotDir(u32),
+    /// A path component is not in its directory
+    NotFound,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Device => write!(f, "failed to read the disk"),
+            Error::Corrupt => write!(f, "filesystem is corrupt"),
+            Error::BlockSize(log) => write!(f, "block size 1024 << {} is not supported", log),
+            Error::DirSize(size) => write!(f, "directory size {} is too large", size),
+            Error::NotDir(number) => write!(f, "inode {} is not a directory", number),
+            Error::NotFound => write!(f, "not found"),
+        }
+    }
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+/// A disk holding the filesystem, read in `BLOCK_SIZE` byte blocks
+pub trait Disk {
+    /// Read whole blocks starting at `block` into `buffer`
+    fn read_at(&mut self, block: u64, buffer: &mut [u8]) -> Result<()>;
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+/// Get the size of a directory to read, which a corrupt inode cannot make larger than the
+/// filesystem of `fs_size` bytes or `MAX_DIR_SIZE`
+fn dir_size(size: u64, fs_size: u64) -> Result<usize> {
+    if size > fs_size || size > MAX_DIR_SIZE {
+        return Err(Error::DirSize(size));
+    }
+    Ok(size as usize)
+}
+
+/// A run of file blocks stored contiguously on disk, or a hole if `physical` is `None`
+struct Run {
+    logical: u64,
+    physical: Option<u64>,
+    count: u64,
+}
+
+pub struct Inode {
+    pub number: u32,
+    mode: u16,
+    size: u64,
+    flags: u32,
+    block: [u8; 60],
+}
+
+impl Inode {
+    pub fn is_dir(&self) -> bool {
+        self.mode & S_IFMT == S_IFDIR
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+pub struct FileSystem<D: Disk> {
+    pub disk: D,
+    /// First block of the filesystem on the disk, in `BLOCK_SIZE` units
+    pub start: u64,
+    block_size: u64,
+    /// Size of the filesystem in bytes
+    size: u64,
+    inodes_per_group: u32,
+    inode_size: u64,
+    desc_size: u64,
+    desc_block: u64,
+}
+
+impl<D: Disk> FileSystem<D> {
+    pub fn open(mut disk: D, block_opt: Option<u64>) -> Result<Self> {
+        let start = block_opt.unwrap_or(0);
+
+        let mut data = [0; BLOCK_SIZE as usize];
+        disk.read_at(start, &mut data)?;
+        let sb = &data[SUPERBLOCK_OFFSET as usize..];
+        if u16_at(sb, 56) != MAGIC {
+            return Err(Error::Corrupt);
+        }
+
+        let log_block_size = u32_at(sb, 24);
+        if log_block_size > 6 {
+            return Err(Error::BlockSize(log_block_size));
+        }
+        let block_size = 1024 << log_block_size;
+        let mut blocks = u32_at(sb, 4) as u64;
+        if u32_at(sb, 96) & INCOMPAT_64BIT != 0 {
+            blocks |= (u32_at(sb, 0x150) as u64) << 32;
+        }
+        let inode_size = if u32_at(sb, 76) >= 1 { u16_at(sb, 88) as u64 } else { 128 };
+        let desc_size = if u32_at(sb, 96) & INCOMPAT_64BIT != 0 { u16_at(sb, 254) as u64 } else { 32 };
+        let inodes_per_group = u32_at(sb, 40);
+        if inode_size < 128 || desc_size < 32 || inodes_per_group == 0 {
+            return Err(Error::Corrupt);
+        }
+
+        Ok(Self {
+            disk,
+            start,
+            block_size,
+            size: blocks * block_size,
+            inodes_per_group,
+            inode_size,
+            desc_size,
+            // The group descriptors follow the block holding the superblock
+            desc_block: if block_size == 1024 { 2 } else { 1 },
+        })
+    }
+
+    /// Read `buffer.len()` bytes at a byte offset into the filesystem
+    fn read_bytes(&mut self, offset: u64, buffer: &mut [u8]) -> Result<()> {
+        let mut block = [0; BLOCK_SIZE as usize];
+        let mut done = 0;
+        while done < buffer.len() {
+            let position = offset + done as u64;
+            let block_offset = (position % BLOCK_SIZE) as usize;
+            self.disk.read_at(self.start + position / BLOCK_SIZE, &mut block)?;
+            let count = cmp::min(buffer.len() - done, BLOCK_SIZE as usize - block_offset);
+            buffer[done..done + count].copy_from_slice(&block[block_offset..block_offset + count]);
+            done += count;
+        }
+        Ok(())
+    }
+
+    fn read_block(&mut self, block: u64) -> Result<Vec<u8>> {
+        let mut data = vec![0; self.block_size as usize];
+        self.read_bytes(block * self.block_size, &mut data)?;
+        Ok(data)
+    }
+
+    pub fn inode(&mut self, number: u32) -> Result<Inode> {
+        if number == 0 {
+            return Err(Error::NotFound);
+        }
+        let group = ((number - 1) / self.inodes_per_group) as u64;
+        let index = ((number - 1) % self.inodes_per_group) as u64;
+
+        let mut desc = vec![0; self.desc_size as usize];
+        self.read_bytes(self.desc_block * self.block_size + group * self.desc_size, &mut desc)?;
+        let mut table = u32_at(&desc, 8) as u64;
+        if self.desc_size >= 64 {
+            table |= (u32_at(&desc, 0x28) as u64) << 32;
+        }
+
+        let mut data = [0; 128];
+        self.read_bytes(table * self.block_size + index * self.inode_size, &mut data)?;
+        let mut block = [0; 60];
+        block.copy_from_slice(&data[40..100]);
+        Ok(Inode {
+            number,
+            mode: u16_at(&data, 0),
+            size: u32_at(&data, 4) as u64 | (u32_at(&data, 108) as u64) << 32,
+            flags: u32_at(&data, 32),
+            block,
+        })
+    }
+
+    /// Collect the runs of an extent tree node, descending into index nodes
+    fn extent_runs(&mut self, node: &[u8], runs: &mut Vec<Run>, depth_left: u32) -> Result<()> {
+        if node.len() < 12 || u16_at(node, 0) != EXTENT_MAGIC || depth_left == 0 {
+            return Err(Error::Corrupt);
+        }
+        let entries = u16_at(node, 2) as usize;
+        let depth = u16_at(node, 6);
+        for i in 0..entries {
+            let entry = node.get(12 + i * 12..24 + i * 12).ok_or(Error::Corrupt)?;
+            if depth == 0 {
+                let len = u16_at(entry, 4);
+                let physical = (u16_at(entry, 6) as u64) << 32 | u32_at(entry, 8) as u64;
+                let (physical, count) = if len > EXTENT_INIT_MAX_LEN {
+                    (None, len - EXTENT_INIT_MAX_LEN)
+                } else {
+                    (Some(physical), len)
+                };
+                runs.push(Run {
+                    logical: u32_at(entry, 0) as u64,
+                    physical,
+                    count: count as u64,
+                });
+            } else {
+                let child = (u16_at(entry, 8) as u64) << 32 | u32_at(entry, 4) as u64;
+                let child = self.read_block(child)?;
+                self.extent_runs(&child, runs, depth_left - 1)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Collect the runs of an indirect block map, `level` levels deep
+    fn indirect_runs(&mut self, block: u32, level: u32, logical: &mut u64, runs: &mut Vec<Run>) -> Result<()> {
+        let per_block = self.block_size / 4;
+        let mut span = 1;
+        for _ in 0..level {
+            span *= per_block;
+        }
+        if block == 0 {
+            // A hole covering everything this block would have mapped
+            *logical += span;
+            return Ok(());
+        }
+        if level == 0 {
+            // Merge with the previous run if the blocks are contiguous, which they usually are
+            match runs.last_mut() {
+                Some(Run { logical: last_logical, physical: Some(last_physical), count })
+                    if *last_logical + *count == *logical && *last_physical + *count == block as u64 => *count += 1,
+                _ => runs.push(Run { logical: *logical, physical: Some(block as u64), count: 1 }),
+            }
+            *logical += 1;
+            return Ok(());
+        }
+
+        let data = self.read_block(block as u64)?;
+        for i in 0..per_block as usize {
+            self.indirect_runs(u32_at(&data, i * 4), level - 1, logical, runs)?;
+        }
+        Ok(())
+    }
+
+    fn runs(&mut self, inode: &Inode) -> Result<Vec<Run>> {
+        let mut runs = Vec::new();
+        if inode.flags & EXTENTS_FL != 0 {
+            let block = inode.block;
+            // The tree is at most 5 levels deep
+            self.extent_runs(&block, &mut runs, 6)?;
+        } else {
+            // u64::div_ceil is newer than the pinned toolchain
+            #[allow(clippy::manual_div_ceil)]
+            let blocks = (inode.size + self.block_size - 1) / self.block_size;
+            let mut logical = 0;
+            for i in 0..15 {
+                if logical >= blocks {
+                    break;
+                }
+                let level = if i < 12 { 0 } else { i as u32 - 11 };
+                self.indirect_runs(u32_at(&inode.block, i * 4), level, &mut logical, &mut runs)?;
+            }
+        }
+        Ok(runs)
+    }
+
+    /// Read from the file of `inode` at `offset`, returning the number of bytes read
+    pub fn read(&mut self, inode: &Inode, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        let runs = self.runs(inode)?;
+        let end = cmp::min(inode.size, offset + buffer.len() as u64);
+        let mut position = offset;
+        while position < end {
+            let logical = position / self.block_size;
+            let block_offset = position % self.block_size;
+            let count = cmp::min(self.block_size - block_offset, end - position) as usize;
+            let done = (position - offset) as usize;
+
+            let physical = runs.iter()
+                .find(|run| logical >= run.logical && logical < run.logical + run.count)
+                .and_then(|run| run.physical.map(|physical| physical + logical - run.logical));
+            match physical {
+                Some(physical) => self.read_bytes(
+                    physical * self.block_size + block_offset,
+                    &mut buffer[done..done + count]
+                )?,
+                None => for byte in buffer[done..done + count].iter_mut() {
+                    *byte = 0;
+                },
+            }
+
+            position += count as u64;
+        }
+        Ok((end.saturating_sub(offset)) as usize)
+    }
+
+    /// Find the inode number of `name` in the directory `dir`
+    fn find_entry(&mut self, dir: &Inode, name: &str) -> Result<u32> {
+        let mut data = vec![0; dir_size(dir.size, self.size)?];
+        self.read(dir, 0, &mut data)?;
+
+        let mut i = 0;
+        while i + 8 <= data.len() {
+            let number = u32_at(&data, i);
+            let rec_len = u16_at(&data, i + 4) as usize;
+            let name_len = data[i + 6] as usize;
+            if rec_len < 8 {
+                return Err(Error::Corrupt);
+            }
+            if number != 0 && data.get(i + 8..i + 8 + name_len) == Some(name.as_bytes()) {
+                return Ok(number);
+            }
+            i += rec_len;
+        }
+        Err(Error::NotFound)
+    }
+
+    /// Walk a `/` separated path from the root directory, returning the final inode
+    pub fn find_path(&mut self, path: &str) -> Result<Inode> {
+        let mut inode = self.inode(ROOT_INODE)?;
+        for component in path.split('/').filter(|component| !component.is_empty()) {
+            if !inode.is_dir() {
+                return Err(Error::NotDir(inode.number));
+            }
+            let number = self.find_entry(&inode, component)?;
+            inode = self.inode(number)?;
+        }
+        Ok(inode)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// A disk that only stores the blocks written to it, and reads zeroes elsewhere
+    #[derive(Default)]
+    struct SparseDisk {
+        blocks: HashMap<u64, Vec<u8>>,
+    }
+
+    impl SparseDisk {
+        fn write(&mut self, offset: u64, data: &[u8]) {
+            for (i, &byte) in data.iter().enumerate() {
+                let position = offset + i as u64;
+                let block = self.blocks.entry(position / BLOCK_SIZE).or_insert_with(|| vec![0; BLOCK_SIZE as usize]);
+                block[(position % BLOCK_SIZE) as usize] = byte;
+            }
+        }
+    }
+
+    impl Disk for SparseDisk {
+        fn read_at(&mut self, block: u64, buffer: &mut [u8]) -> Result<()> {
+            for (i, chunk) in buffer.chunks_mut(BLOCK_SIZE as usize).enumerate() {
+                match self.blocks.get(&(block + i as u64)) {
+                    Some(data) => chunk.copy_from_slice(&data[..chunk.len()]),
+                    None => chunk.iter_mut().for_each(|byte| *byte = 0),
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// Partition start, in disk blocks
+    const START: u64 = 256;
+    /// File block past 4 GiB, and the filesystem block past 5 GiB it is stored in
+    const FILE_BLOCK: u64 = 0x11_0000;
+    const DISK_BLOCK: u64 = 0x14_0000;
+
+    /// Write an extent tree root with one extent to the 60 bytes of `i_block`
+    fn extent(disk: &mut SparseDisk, inode: u64, logical: u64, physical: u64) {
+        let mut block = [0; 24];
+        block[0..2].copy_from_slice(&EXTENT_MAGIC.to_le_bytes());
+        block[2..4].copy_from_slice(&1u16.to_le_bytes());
+        block[4..6].copy_from_slice(&4u16.to_le_bytes());
+        block[12..16].copy_from_slice(&(logical as u32).to_le_bytes());
+        block[16..18].copy_from_slice(&1u16.to_le_bytes());
+        block[18..20].copy_from_slice(&((physical >> 32) as u16).to_le_bytes());
+        block[20..24].copy_from_slice(&(physical as u32).to_le_bytes());
+        disk.write(inode + 40, &block);
+    }
+
+    /// An ext4 filesystem with 4 KiB blocks holding `/kernel`, whose only data block is
+    /// past 4 GiB in the file and past 5 GiB on the disk
+    fn sparse_image() -> SparseDisk {
+        let mut disk = SparseDisk::default();
+        let base = START * BLOCK_SIZE;
+
+        let sb = base + SUPERBLOCK_OFFSET;
+        disk.write(sb + 4, &(DISK_BLOCK as u32 + 1).to_le_bytes());
+        disk.write(sb + 24, &2u32.to_le_bytes());
+        disk.write(sb + 40, &16u32.to_le_bytes());
+        disk.write(sb + 56, &MAGIC.to_le_bytes());
+        disk.write(sb + 76, &1u32.to_le_bytes());
+        disk.write(sb + 88, &128u16.to_le_bytes());
+
+        // The group descriptors are in block 1, with the inode table in block 2
+        disk.write(base + BLOCK_SIZE + 8, &2u32.to_le_bytes());
+
+        let root = base + 2 * BLOCK_SIZE + (ROOT_INODE as u64 - 1) * 128;
+        disk.write(root, &(S_IFDIR | 0o755).to_le_bytes());
+        disk.write(root + 4, &(BLOCK_SIZE as u32).to_le_bytes());
+        disk.write(root + 32, &EXTENTS_FL.to_le_bytes());
+        extent(&mut disk, root, 0, 3);
+
+        let size = (FILE_BLOCK + 1) * BLOCK_SIZE;
+        let file = base + 2 * BLOCK_SIZE + 11 * 128;
+        disk.write(file, &0o100644u16.to_le_bytes());
+        disk.write(file + 4, &(size as u32).to_le_bytes());
+        disk.write(file + 108, &((size >> 32) as u32).to_le_bytes());
+        disk.write(file + 32, &EXTENTS_FL.to_le_bytes());
+        extent(&mut disk, file, FILE_BLOCK, DISK_BLOCK);
+
+        let dir = base + 3 * BLOCK_SIZE;
+        disk.write(dir, &12u32.to_le_bytes());
+        disk.write(dir + 4, &(BLOCK_SIZE as u16).to_le_bytes());
+        disk.write(dir + 6, &[6, 1]);
+        disk.write(dir + 8, b"kernel");
+
+        disk.write(base + DISK_BLOCK * BLOCK_SIZE + 100, b"past five GiB");
+        disk
+    }
+
+    #[test]
+    fn read_past_4g() {
+        let mut fs = FileSystem::open(sparse_image(), Some(START)).unwrap();
+        let inode = fs.find_path("/kernel").unwrap();
+        assert_eq!(inode.size(), 0x1_1000_1000);
+
+        let mut buffer = [0xff; 13];
+        assert_eq!(fs.read(&inode, FILE_BLOCK * BLOCK_SIZE + 100, &mut buffer), Ok(13));
+        assert_eq!(&buffer, b"past five GiB");
+
+        // Before the extent is a hole, which reads as zeroes
+        let mut buffer = [0xff; 16];
+        assert_eq!(fs.read(&inode, 0x1_0000_0000, &mut buffer), Ok(16));
+        assert_eq!(buffer, [0; 16]);
+    }
+
+    #[test]
+    fn path_errors() {
+        let mut fs = FileSystem::open(sparse_image(), Some(START)).unwrap();
+        assert_eq!(fs.find_path("/missing").err(), Some(Error::NotFound));
+        assert_eq!(fs.find_path("/kernel/missing").err(), Some(Error::NotDir(12)));
+    }
+
+    #[test]
+    fn dir_size_in_bounds() {
+        assert_eq!(dir_size(4096, 1024 * 1024 * 1024).ok(), Some(4096));
+    }
+
+    #[test]
+    fn dir_size_corrupt() {
+        assert!(matches!(dir_size(u64::MAX, 1024 * 1024 * 1024), Err(Error::DirSize(_))));
+        assert!(matches!(dir_size(MAX_DIR_SIZE + 1, u64::MAX), Err(Error::DirSize(_))));
+        assert!(matches!(dir_size(8192, 4096), Err(Error::DirSize(_))));
+    }
+}
@@ -0,0 +1,45 @@
+//! Matching file names from the firmware
+
+/// Compare a UCS-2 file name with a path component, ignoring ASCII case like FAT does
+pub fn name_matches(name: &[u16], component: &str) -> bool {
+    let len = name.iter().position(|&w| w == 0).unwrap_or(name.len());
+    let mut chars = component.chars();
+    for &w in &name[..len] {
+        match chars.next() {
+            Some(c) if (c as u32) < 0x80 && (w as u32) < 0x80 => {
+                if !(c as u8).eq_ignore_ascii_case(&(w as u8)) {
+                    return false;
+                }
+            },
+            Some(c) if c as u32 == w as u32 => (),
+            _ => return false,
+        }
+    }
+    chars.next().is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A NUL terminated UCS-2 name, like `FileInfo.FileName`
+    fn ucs2(name: &str) -> Vec<u16> {
+        name.encode_utf16().chain(Some(0)).collect()
+    }
+
+    #[test]
+    fn long_name_matches() {
+        assert!(name_matches(&ucs2("vmlinuz-redox-0.1.0"), "vmlinuz-redox-0.1.0"));
+        assert!(!name_matches(&ucs2("vmlinuz-redox-0.1.0"), "vmlinuz-redox-0.1"));
+        assert!(!name_matches(&ucs2("vmlinuz-redox"), "vmlinuz-redox-0.1.0"));
+    }
+
+    #[test]
+    fn name_ignores_ascii_case() {
+        assert!(name_matches(&ucs2("KERNEL"), "kernel"));
+        assert!(name_matches(&ucs2("Redox-Kernel.Efi"), "redox-kernel.EFI"));
+        // Only ASCII is folded, like FAT
+        assert!(!name_matches(&ucs2("\u{c4}"), "\u{e4}"));
+        assert!(name_matches(&ucs2("\u{e4}"), "\u{e4}"));
+    }
+}
@@ -0,0 +1,106 @@
+//! Placing images on the screen
+
+use core::cmp;
+
+/// Get the part of a `w` by `h` image at `x`, `y` that is on a `screen_w` by `screen_h`
+/// screen, as its left, top, width, and height in image coordinates
+pub fn visible(w: u32, h: u32, x: i32, y: i32, screen_w: u32, screen_h: u32) -> Option<(u32, u32, u32, u32)> {
+    let left = cmp::max(0, -(x as i64));
+    let top = cmp::max(0, -(y as i64));
+    let right = cmp::min(w as i64, screen_w as i64 - x as i64);
+    let bottom = cmp::min(h as i64, screen_h as i64 - y as i64);
+    if left >= right || top >= bottom {
+        return None;
+    }
+    Some((left as u32, top as u32, (right - left) as u32, (bottom - top) as u32))
+}
+
+/// A run of pixels in one row of an image, and where it goes on the screen
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Row {
+    /// Index of the first pixel in the image data
+    pub offset: usize,
+    /// Number of pixels
+    pub len: usize,
+    pub x: i32,
+    pub y: i32,
+}
+
+/// Get the rows of a `w` by `h` image at `x`, `y` that are on a `screen_w` by `screen_h`
+/// screen, cropped to it
+pub fn visible_rows(w: u32, h: u32, x: i32, y: i32, screen_w: u32, screen_h: u32) -> impl Iterator<Item = Row> {
+    let (left, top, len, rows) = visible(w, h, x, y, screen_w, screen_h).unwrap_or((0, 0, 0, 0));
+    (top..top + rows).map(move |row| Row {
+        offset: row as usize * w as usize + left as usize,
+        len: len as usize,
+        x: x + left as i32,
+        y: y + row as i32,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wide_image_centered() {
+        // A 2000px splash centered on an 800px screen starts 600px left of it
+        assert_eq!(visible(2000, 100, -600, 0, 800, 600), Some((600, 0, 800, 100)));
+    }
+
+    #[test]
+    fn wide_image_at_origin() {
+        assert_eq!(visible(2000, 2000, 0, 0, 800, 600), Some((0, 0, 800, 600)));
+    }
+
+    #[test]
+    fn image_off_screen() {
+        assert_eq!(visible(2000, 100, 800, 0, 800, 600), None);
+        assert_eq!(visible(2000, 100, -2000, 0, 800, 600), None);
+        assert_eq!(visible_rows(2000, 100, 800, 0, 800, 600).count(), 0);
+    }
+
+    /// Draw a `w` by `h` image whose pixels are their own index at `x`, `y` on an 800x600
+    /// screen, which is `u32::MAX` where nothing was drawn
+    fn draw(w: u32, h: u32, x: i32, y: i32) -> Vec<u32> {
+        let image: Vec<u32> = (0..w * h).collect();
+        let mut screen = vec![u32::MAX; 800 * 600];
+        for row in visible_rows(w, h, x, y, 800, 600) {
+            let start = row.y as usize * 800 + row.x as usize;
+            screen[start..start + row.len].copy_from_slice(&image[row.offset..row.offset + row.len]);
+        }
+        screen
+    }
+
+    #[test]
+    fn wide_image_pixels() {
+        // A 2000x100 image centered horizontally and 50px from the bottom
+        let screen = draw(2000, 100, -600, 550);
+        for sy in 0..600 {
+            for sx in 0..800 {
+                let expected = if sy >= 550 {
+                    (sy - 550) * 2000 + sx + 600
+                } else {
+                    u32::MAX
+                };
+                assert_eq!(screen[(sy * 800 + sx) as usize], expected, "pixel {}, {}", sx, sy);
+            }
+        }
+    }
+
+    #[test]
+    fn tall_image_pixels() {
+        // Starting above and left of the screen, and ending inside it
+        let screen = draw(300, 1000, -100, -200);
+        for sy in 0..600 {
+            for sx in 0..800 {
+                let expected = if sx < 200 {
+                    (sy + 200) * 300 + sx + 100
+                } else {
+                    u32::MAX
+                };
+                assert_eq!(screen[(sy * 800 + sx) as usize], expected, "pixel {}, {}", sx, sy);
+            }
+        }
+    }
+}
@@ -0,0 +1,49 @@
+//! Kernel checksums
+
+use core::str;
+
+/// CRC-32 as used by zlib and gzip, with the reflected 0xEDB88320 polynomial
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// Parse a CRC-32 written as 8 hexadecimal digits, ignoring surrounding whitespace
+pub fn parse_crc32(data: &[u8]) -> Option<u32> {
+    let digits = str::from_utf8(data).ok()?.trim();
+    // from_str_radix would also take a sign
+    if digits.len() != 8 || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    u32::from_str_radix(digits, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_known_vectors() {
+        assert_eq!(crc32(b""), 0x0000_0000);
+        assert_eq!(crc32(b"a"), 0xE8B7_BE43);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b"The quick brown fox jumps over the lazy dog"), 0x414F_A339);
+    }
+
+    #[test]
+    fn parse_crc32_digits() {
+        assert_eq!(parse_crc32(b"cbf43926\n"), Some(0xCBF4_3926));
+        assert_eq!(parse_crc32(b"  CBF43926"), Some(0xCBF4_3926));
+        assert_eq!(parse_crc32(b"cbf4392"), None);
+        assert_eq!(parse_crc32(b"+bf43926"), None);
+        assert_eq!(parse_crc32(b"cbf4392g"), None);
+        assert_eq!(parse_crc32(&[0xff; 8]), None);
+    }
+}
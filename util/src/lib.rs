@@ -0,0 +1,15 @@
+//! Parts of the bootloader that do not use the firmware, so they can be tested on the host
+//! with `cargo test`
+
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+pub mod compress;
+pub mod disk;
+pub mod ext;
+pub mod font;
+pub mod fs;
+pub mod image;
+pub mod integrity;
+pub mod text;
@@ -0,0 +1,287 @@
+//! Text console state: the cursor, line wrapping, scrolling, and ANSI colors
+
+/// A grid of character cells that text is drawn into
+pub trait Cells {
+    /// Fill the cell at `column` and `row` with the background
+    fn clear(&mut self, column: i32, row: i32);
+    /// Draw `c` over the cell at `column` and `row` in the ARGB color `fg`
+    fn glyph(&mut self, column: i32, row: i32, c: char, fg: u32);
+    /// Move every row up by one, clearing the last
+    fn scroll(&mut self);
+}
+
+/// Foreground color after a reset
+pub const DEFAULT_FG: u32 = 0xFFFF_FFFF;
+
+/// Get the column and row for the next glyph, wrapping only when it would go past the last
+/// column, so a line that exactly fills the row does not leave a blank one
+fn glyph_cell(column: i32, row: i32, cols: usize) -> (i32, i32) {
+    if column as usize >= cols {
+        (0, row + 1)
+    } else {
+        (column, row)
+    }
+}
+
+/// Get one of the eight ANSI colors as ARGB, optionally in its bright variant
+fn ansi_color(index: u32, bright: bool) -> u32 {
+    let (lo, hi) = if bright { (0x55, 0xff) } else { (0x00, 0xaa) };
+    let channel = |bit: u32| if index & bit != 0 { hi } else { lo };
+    let (r, g, b) = match index {
+        0 if !bright => (0x00, 0x00, 0x00),
+        7 if !bright => (0xaa, 0xaa, 0xaa),
+        _ => (channel(1), channel(2), channel(4)),
+    };
+    0xFF00_0000 | r << 16 | g << 8 | b
+}
+
+/// State of the escape sequence parser
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Escape {
+    None,
+    Esc,
+    Csi(u32),
+}
+
+/// What a write did to the cells
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Written {
+    /// Rows scrolled off the top
+    pub scrolled: usize,
+    /// Whether any cell was drawn or cleared
+    pub changed: bool,
+}
+
+/// Cursor, size, and color of a text console
+pub struct Terminal {
+    pub column: i32,
+    pub row: i32,
+    pub cols: usize,
+    pub rows: usize,
+    /// Foreground color, as ARGB
+    pub fg: u32,
+    escape: Escape,
+}
+
+impl Terminal {
+    pub fn new(cols: usize, rows: usize) -> Terminal {
+        Terminal {
+            column: 0,
+            row: 0,
+            cols,
+            rows,
+            fg: DEFAULT_FG,
+            escape: Escape::None,
+        }
+    }
+
+    /// Apply a Select Graphic Rendition parameter, ignoring unsupported ones
+    fn sgr(&mut self, param: u32) {
+        match param {
+            0 | 39 => self.fg = DEFAULT_FG,
+            30 ..= 37 => self.fg = ansi_color(param - 30, false),
+            90 ..= 97 => self.fg = ansi_color(param - 90, true),
+            _ => (),
+        }
+    }
+
+    /// Feed a character to the escape sequence parser, returning true if it was consumed
+    fn escape(&mut self, c: char) -> bool {
+        match self.escape {
+            Escape::None => if c == '\x1b' {
+                self.escape = Escape::Esc;
+            } else {
+                return false;
+            },
+            Escape::Esc => if c == '[' {
+                self.escape = Escape::Csi(0);
+            } else {
+                self.escape = Escape::None;
+            },
+            Escape::Csi(param) => match c {
+                '0' ..= '9' => {
+                    let digit = c as u32 - '0' as u32;
+                    self.escape = Escape::Csi(param.saturating_mul(10).saturating_add(digit));
+                },
+                ';' => {
+                    self.sgr(param);
+                    self.escape = Escape::Csi(0);
+                },
+                'm' => {
+                    self.sgr(param);
+                    self.escape = Escape::None;
+                },
+                '\x40' ..= '\x7e' => {
+                    // Unknown final byte, ignore the whole sequence
+                    self.escape = Escape::None;
+                },
+                _ => (),
+            },
+        }
+        true
+    }
+
+    /// Scroll until the cursor row is inside the region, returning the rows scrolled
+    fn scroll_to_cursor<C: Cells>(&mut self, cells: &mut C) -> usize {
+        let mut scrolled = 0;
+        while self.rows > 0 && self.row as usize >= self.rows {
+            cells.scroll();
+            self.row -= 1;
+            scrolled += 1;
+        }
+        scrolled
+    }
+
+    /// Write characters at the cursor, interpreting backspace, carriage return, line feed,
+    /// and SGR color escapes
+    pub fn write<C: Cells, I: IntoIterator<Item = char>>(&mut self, cells: &mut C, chars: I) -> Written {
+        let mut written = Written::default();
+
+        for c in chars {
+            if self.escape(c) {
+                continue;
+            }
+
+            written.scrolled += self.scroll_to_cursor(cells);
+
+            match c {
+                '\x08' => if self.column > 0 {
+                    cells.clear(self.column, self.row);
+                    self.column -= 1;
+                    written.changed = true;
+                },
+                '\r' => {
+                    self.column = 0;
+                },
+                '\n' => {
+                    self.row += 1;
+                },
+                _ => {
+                    let (column, row) = glyph_cell(self.column, self.row, self.cols);
+                    if row != self.row {
+                        self.column = column;
+                        self.row = row;
+                        written.scrolled += self.scroll_to_cursor(cells);
+                    }
+
+                    cells.clear(self.column, self.row);
+                    cells.glyph(self.column, self.row, c, self.fg);
+                    self.column += 1;
+                    written.changed = true;
+                }
+            }
+        }
+
+        written
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A grid of characters standing in for the display
+    struct Grid {
+        cols: usize,
+        cells: Vec<Vec<(char, u32)>>,
+    }
+
+    impl Grid {
+        fn new(cols: usize, rows: usize) -> Grid {
+            Grid {
+                cols,
+                cells: vec![vec![(' ', 0); cols]; rows],
+            }
+        }
+
+        fn line(&self, row: usize) -> String {
+            self.cells[row].iter().map(|&(c, _)| c).collect::<String>().trim_end().to_string()
+        }
+    }
+
+    impl Cells for Grid {
+        fn clear(&mut self, column: i32, row: i32) {
+            self.cells[row as usize][column as usize] = (' ', 0);
+        }
+
+        fn glyph(&mut self, column: i32, row: i32, c: char, fg: u32) {
+            self.cells[row as usize][column as usize] = (c, fg);
+        }
+
+        fn scroll(&mut self) {
+            self.cells.remove(0);
+            self.cells.push(vec![(' ', 0); self.cols]);
+        }
+    }
+
+    #[test]
+    fn long_line_wraps() {
+        let mut grid = Grid::new(80, 25);
+        let mut term = Terminal::new(80, 25);
+        let line: String = (0..200).map(|i| (b'a' + (i % 26) as u8) as char).collect();
+
+        let written = term.write(&mut grid, line.chars());
+
+        assert_eq!(written, Written { scrolled: 0, changed: true });
+        assert_eq!((term.column, term.row), (40, 2));
+        assert_eq!(grid.line(0), &line[..80]);
+        assert_eq!(grid.line(1), &line[80..160]);
+        assert_eq!(grid.line(2), &line[160..]);
+        assert_eq!(grid.line(3), "");
+    }
+
+    #[test]
+    fn full_row_does_not_wrap() {
+        let mut grid = Grid::new(80, 25);
+        let mut term = Terminal::new(80, 25);
+
+        term.write(&mut grid, "x".repeat(80).chars());
+        assert_eq!((term.column, term.row), (80, 0));
+
+        term.write(&mut grid, "y".chars());
+        assert_eq!((term.column, term.row), (1, 1));
+        assert_eq!(grid.line(1), "y");
+    }
+
+    #[test]
+    fn scrolls_at_bottom() {
+        let mut grid = Grid::new(10, 3);
+        let mut term = Terminal::new(10, 3);
+
+        let written = term.write(&mut grid, "one\r\ntwo\r\nthree\r\nfour".chars());
+
+        assert_eq!(written.scrolled, 1);
+        assert_eq!(term.row, 2);
+        assert_eq!(grid.line(0), "two");
+        assert_eq!(grid.line(1), "three");
+        assert_eq!(grid.line(2), "four");
+    }
+
+    #[test]
+    fn backspace_clears() {
+        let mut grid = Grid::new(10, 3);
+        let mut term = Terminal::new(10, 3);
+
+        term.write(&mut grid, "ab\x08".chars());
+
+        // The cell at the cursor is cleared before it moves back
+        assert_eq!(term.column, 1);
+        assert_eq!(grid.line(0), "ab");
+        term.write(&mut grid, "\x08c".chars());
+        assert_eq!(grid.line(0), "c");
+    }
+
+    #[test]
+    fn sgr_colors() {
+        let mut grid = Grid::new(10, 3);
+        let mut term = Terminal::new(10, 3);
+
+        term.write(&mut grid, "\x1b[31mr\x1b[1;92mg\x1b[0md\x1b[2Jx".chars());
+
+        assert_eq!(grid.line(0), "rgdx");
+        assert_eq!(grid.cells[0][0].1, 0xFFAA_0000);
+        assert_eq!(grid.cells[0][1].1, 0xFF55_FF55);
+        assert_eq!(grid.cells[0][2].1, DEFAULT_FG);
+        assert_eq!(grid.cells[0][3].1, DEFAULT_FG);
+    }
+}